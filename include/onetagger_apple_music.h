@@ -0,0 +1,29 @@
+/* C API of onetagger-apple-music, built with
+   `cargo rustc --release --lib --crate-type cdylib --no-default-features --features ffi` */
+#ifndef ONETAGGER_APPLE_MUSIC_H
+#define ONETAGGER_APPLE_MUSIC_H
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct AppleMusic AppleMusic;
+
+/* Create client with Media User Token, NULL if it is invalid. Free with am_client_free */
+AppleMusic *am_client_new(const char *media_user_token);
+void am_client_free(AppleMusic *client);
+
+/* Returned strings are freed with am_string_free, NULL on failure */
+char *am_search(const AppleMusic *client, const char *query);
+char *am_song_json(const AppleMusic *client, const char *song_id);
+char *am_lyrics_lrc(const AppleMusic *client, const char *song_id);
+void am_string_free(char *s);
+
+/* Message of the last failed call on this thread, owned by the library */
+const char *am_last_error(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,156 @@
+//! Access token and storefront of the media user token
+
+use std::error::Error;
+use std::fmt;
+use std::sync::{Mutex, OnceLock};
+use fancy_regex::Regex;
+use serde_json::Value;
+use reqwest::StatusCode;
+use reqwest::header::{ETAG, IF_NONE_MATCH};
+use crate::{AppleMusic, AppleMusicError};
+
+/// Web token couldn't be scraped from music.apple.com
+const WEB_TOKEN_ERROR: &str = "Unable to find web token on music.apple.com";
+
+/// Web player bundle the token was last found in, shared by all clients
+static WEB_BUNDLE: Mutex<Option<WebBundle>> = Mutex::new(None);
+
+struct WebBundle {
+    url: String,
+    etag: Option<String>,
+    token: String,
+}
+
+impl AppleMusic {
+    /// Fetch the auth token
+    pub fn fetch_token(&self) -> Result<(), Box<dyn Error>> {
+        let token = match self.cached_web_token() {
+            Some(token) => token,
+            None => self.scrape_web_token()?,
+        };
+        *self.access_token.lock().unwrap() = Some(token.clone());
+        // Fetch catalog
+        let response = self.client.get("https://amp-api.music.apple.com/v1/me/account?meta=subscription&challenge%5BsubscriptionCapabilities%5D=voice%2Cpremium")
+            .bearer_auth(token)
+            .send()
+            .map_err(AppleMusicError::from)?;
+        if response.status() == StatusCode::UNAUTHORIZED || response.status() == StatusCode::FORBIDDEN {
+            return Err(AppleMusicError::AuthExpired.into());
+        }
+        let r: Value = response.error_for_status()?.json()?;
+        // Check sub
+        if !r["meta"]["subscription"]["active"].as_bool().unwrap_or(false) {
+            return Err("The account of the Media User Token has no active Apple Music subscription".into());
+        }
+        // Get storefront
+        let storefront = r["meta"]["subscription"]["storefront"].as_str()
+            .ok_or("Unable to get the account storefront: make sure Apple Music is available in the account's country")?;
+        debug!("Storefront: {storefront}");
+        // Keep storefront set in options or by `with_storefront`
+        self.catalog.lock().unwrap().get_or_insert(storefront.to_string());
+        Ok(())
+    }
+
+    /// Revalidate the last bundle, `None` if it changed or is gone
+    fn cached_web_token(&self) -> Option<String> {
+        let mut bundle = WEB_BUNDLE.lock().unwrap();
+        let cached = bundle.as_mut()?;
+        debug!("Revalidating Apple Music web bundle {}", cached.url);
+        let mut request = self.client.get(&cached.url);
+        if let Some(etag) = cached.etag.as_deref() {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+        let response = request.send().ok()?;
+        if response.status() == StatusCode::NOT_MODIFIED {
+            return Some(cached.token.clone());
+        }
+        let etag = response.headers().get(ETAG).map(|e| e.to_str().ok()).flatten().map(String::from);
+        let token = find_web_token(&response.error_for_status().ok()?.text().ok()?).ok()?;
+        cached.etag = etag;
+        cached.token = token.clone();
+        Some(token)
+    }
+
+    /// Find the web player bundle on the search page and get the token from it
+    fn scrape_web_token(&self) -> Result<String, Box<dyn Error>> {
+        static INDEX_JS: OnceLock<Regex> = OnceLock::new();
+        debug!("Fetching Apple Music token");
+        let body = self.client.get("https://music.apple.com/us/search").send().map_err(AppleMusicError::from)?.text()?;
+        let re = INDEX_JS.get_or_init(|| Regex::new("(?<=index\\.)(.*?)(?=\\.js\")").unwrap());
+        let index_js = re.captures(&body)?.map(|c| c.get(1)).flatten().ok_or(AppleMusicError::SchemaChanged(WEB_TOKEN_ERROR.to_string()))?.as_str();
+        let url = format!("https://music.apple.com/assets/index.{index_js}.js");
+        let response = self.client.get(&url).send().map_err(AppleMusicError::from)?;
+        let etag = response.headers().get(ETAG).map(|e| e.to_str().ok()).flatten().map(String::from);
+        let token = find_web_token(&response.text()?)?;
+        *WEB_BUNDLE.lock().unwrap() = Some(WebBundle { url, etag, token: token.clone() });
+        Ok(token)
+    }
+
+    /// Current access token, `None` before [`AppleMusic::fetch_token`]
+    pub fn access_token(&self) -> Option<String> {
+        self.access_token.lock().unwrap().clone()
+    }
+
+    /// Current storefront (`us`, `gb`, ...), `None` before [`AppleMusic::fetch_token`]
+    pub fn storefront(&self) -> Option<String> {
+        self.catalog.lock().unwrap().clone()
+    }
+
+    /// Cheap pre-run validation: token, storefront and a minimal search in it and in `extra_storefronts`
+    pub fn health_check(&self, extra_storefronts: &[&str]) -> HealthReport {
+        let mut report = HealthReport { checks: vec![] };
+        if let Err(e) = self.fetch_token() {
+            report.checks.push(("token".to_string(), Err(e.to_string())));
+            return report;
+        }
+        report.checks.push(("token".to_string(), Ok("valid, subscription active".to_string())));
+        let storefront = match self.storefront() {
+            Some(storefront) => storefront,
+            None => {
+                report.checks.push(("storefront".to_string(), Err("not resolved".to_string())));
+                return report;
+            }
+        };
+        report.checks.push(("storefront".to_string(), Ok(storefront.to_string())));
+        for storefront in [storefront.as_str()].into_iter().chain(extra_storefronts.iter().copied()) {
+            let result = self.with_storefront(storefront).get::<Value>("search", &[("term", "love"), ("types", "songs"), ("limit", "1")])
+                .map(|_| "ok".to_string())
+                .map_err(|e| e.to_string());
+            report.checks.push((format!("search in {storefront}"), result));
+        }
+        report
+    }
+}
+
+/// Outcome of [`AppleMusic::health_check`], check name and result
+#[derive(Debug, Clone)]
+pub struct HealthReport {
+    pub checks: Vec<(String, Result<String, String>)>,
+}
+
+impl HealthReport {
+    /// All checks passed
+    pub fn ok(&self) -> bool {
+        self.checks.iter().all(|(_, r)| r.is_ok())
+    }
+}
+
+impl fmt::Display for HealthReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, result) in &self.checks {
+            match result {
+                Ok(message) => writeln!(f, "[ok] {name}: {message}")?,
+                Err(e) => writeln!(f, "[failed] {name}: {e}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Token in the web player bundle
+fn find_web_token(index_js: &str) -> Result<String, Box<dyn Error>> {
+    static TOKEN: OnceLock<Regex> = OnceLock::new();
+    let re = TOKEN.get_or_init(|| Regex::new("(?=eyJh)(.*?)(?=\")").unwrap());
+    let token = re.captures(index_js)?.map(|c| c.get(1)).flatten().ok_or(AppleMusicError::SchemaChanged(WEB_TOKEN_ERROR.to_string()))?;
+    Ok(token.as_str().to_string())
+}
@@ -0,0 +1,124 @@
+//! Debug / scripting CLI for the Apple Music client
+//!
+//! ```text
+//! APPLE_MUSIC_TOKEN=<media user token> apple-music-cli search "artist title"
+//! ```
+
+use std::error::Error;
+use onetagger_apple_music::{AppleMusic, LocalTrack, import_media_user_tokens};
+use serde::Serialize;
+
+const USAGE: &str = "Usage: apple-music-cli [--token <media user token>] [--language <en_GB>] <command>
+
+Commands:
+    token              Fetch access token and storefront
+    import-token       Find media user tokens stored by Cider / Apple Music Electron
+    health [sf...]     Check token, storefront and search (and in extra storefronts)
+    search <query>     Search songs
+    song <id>          Get song
+    album <id>         Get album
+    album-report <id>  Get album report (tracklist with ISRCs, UPC, label, artwork)
+    lyrics <id>        Get synced lyrics as LRC
+    lyrics <id> --json Get karaoke lyrics as JSON
+    recent [count]     Get recently played songs (default 30)
+    diff <report>      Compare songs in diagnostics report with library (JSON, CSV with --csv)
+
+Media user token can also be set using the APPLE_MUSIC_TOKEN environment variable, or is imported from Cider if missing.";
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("Error: {e}");
+        std::process::exit(1);
+    }
+}
+
+fn run() -> Result<(), Box<dyn Error>> {
+    let mut args = std::env::args().skip(1);
+    let mut token = std::env::var("APPLE_MUSIC_TOKEN").ok();
+    let mut language = None;
+    let mut json = false;
+    let mut csv = false;
+    let mut positional = vec![];
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--token" => token = Some(args.next().ok_or("Missing --token value")?),
+            "--language" => language = Some(args.next().ok_or("Missing --language value")?),
+            "--json" => json = true,
+            "--csv" => csv = true,
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                return Ok(());
+            }
+            _ => positional.push(arg),
+        }
+    }
+    let command = positional.first().ok_or(USAGE)?.as_str();
+    if command == "import-token" {
+        let tokens = import_media_user_tokens();
+        for imported in &tokens {
+            println!("{} ({}): {}", imported.source, imported.path.display(), imported.token);
+        }
+        return match tokens.is_empty() {
+            true => Err("No media user token found".into()),
+            false => Ok(())
+        };
+    }
+    let arg = positional.get(1..).map(|a| a.join(" ")).unwrap_or_default();
+    if !["token", "health", "recent"].contains(&command) && arg.is_empty() {
+        return Err(USAGE.into());
+    }
+
+    let token = token.or_else(|| import_media_user_tokens().into_iter().next().map(|t| t.token));
+    let mut am = AppleMusic::new(&token.ok_or("Missing media user token")?)?;
+    if let Some(language) = language {
+        am.set_language(&language);
+    }
+    if command == "health" {
+        let report = am.health_check(&positional[1..].iter().map(|s| s.as_str()).collect::<Vec<_>>());
+        print!("{report}");
+        return match report.ok() {
+            true => Ok(()),
+            false => Err("Health check failed".into())
+        };
+    }
+    am.fetch_token()?;
+
+    match command {
+        "token" => print_json(&serde_json::json!({
+            "accessToken": am.access_token(),
+            "storefront": am.storefront(),
+        }))?,
+        "search" => print_json(&am.search(&arg)?)?,
+        "song" => print_json(&am.song(&arg)?)?,
+        "album" => print_json(&am.album(&arg)?)?,
+        "album-report" => print_json(&am.album_report(&arg)?)?,
+        "diff" => {
+            let report: Vec<serde_json::Value> = serde_json::from_str(&std::fs::read_to_string(&arg)?)?;
+            let local: Vec<LocalTrack> = report.into_iter().filter(|t| t["songId"].is_string()).map(serde_json::from_value).collect::<Result<_, _>>()?;
+            let diff = am.library_diff(&local)?;
+            match csv {
+                true => print!("{}", diff.to_csv()),
+                false => print_json(&diff)?,
+            }
+        }
+        "recent" => print_json(&am.recently_played(match arg.is_empty() {
+            true => 30,
+            false => arg.parse().map_err(|_| "Invalid count")?
+        })?)?,
+        "lyrics" => {
+            let lyrics = am.karaoke_lyrics(&arg)?;
+            match json {
+                true => print_json(&lyrics)?,
+                false => print!("{}", lyrics.to_lrc()),
+            }
+        }
+        _ => return Err(USAGE.into()),
+    }
+    Ok(())
+}
+
+/// Pretty print as JSON to stdout
+fn print_json<T: Serialize>(value: &T) -> Result<(), Box<dyn Error>> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
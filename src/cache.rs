@@ -0,0 +1,125 @@
+//! Bounded in-memory caches with least recently used eviction
+
+use std::collections::{BTreeMap, HashMap};
+
+/// Cache by ID or URL, least recently used entries are evicted once the total weight is over `max_weight`.
+/// Weight is whatever the cache is bounded by (entries, songs, bytes).
+pub(crate) struct LruCache<V> {
+    entries: HashMap<String, Entry<V>>,
+    /// Keys by last use
+    recency: BTreeMap<u64, String>,
+    tick: u64,
+    weight: usize,
+    max_weight: usize,
+}
+
+struct Entry<V> {
+    value: V,
+    used: u64,
+    weight: usize,
+}
+
+impl<V> LruCache<V> {
+    pub fn new(max_weight: usize) -> LruCache<V> {
+        LruCache { entries: HashMap::new(), recency: BTreeMap::new(), tick: 0, weight: 0, max_weight }
+    }
+
+    /// Get entry and mark it as recently used
+    pub fn get(&mut self, key: &str) -> Option<&V> {
+        self.tick += 1;
+        let entry = self.entries.get_mut(key)?;
+        self.recency.remove(&entry.used);
+        self.recency.insert(self.tick, key.to_string());
+        entry.used = self.tick;
+        Some(&entry.value)
+    }
+
+    /// Insert entry and evict old ones over the limit, entries heavier than the limit aren't cached
+    pub fn insert(&mut self, key: String, value: V, weight: usize) {
+        self.remove(&key);
+        if weight > self.max_weight {
+            return;
+        }
+        self.tick += 1;
+        self.recency.insert(self.tick, key.clone());
+        self.entries.insert(key, Entry { value, used: self.tick, weight });
+        self.weight += weight;
+        while self.weight > self.max_weight {
+            match self.recency.pop_first() {
+                Some((_, key)) => self.remove(&key),
+                None => break,
+            }
+        }
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.recency.remove(&entry.used);
+            self.weight -= entry.weight;
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.recency.clear();
+        self.weight = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cache(keys: &[&str]) -> LruCache<u32> {
+        let mut cache = LruCache::new(3);
+        for (i, key) in keys.iter().enumerate() {
+            cache.insert(key.to_string(), i as u32, 1);
+        }
+        cache
+    }
+
+    #[test]
+    fn evicts_least_recently_inserted() {
+        let mut cache = cache(&["a", "b", "c", "d"]);
+        assert!(cache.get("a").is_none());
+        assert_eq!(cache.get("b"), Some(&1));
+        assert_eq!(cache.get("d"), Some(&3));
+        assert_eq!(cache.weight, 3);
+    }
+
+    #[test]
+    fn hit_refreshes_recency() {
+        let mut cache = cache(&["a", "b", "c"]);
+        assert_eq!(cache.get("a"), Some(&0));
+        cache.insert("d".to_string(), 3, 1);
+        assert!(cache.get("b").is_none());
+        assert_eq!(cache.get("a"), Some(&0));
+    }
+
+    #[test]
+    fn heavier_than_capacity_is_not_cached() {
+        let mut cache = cache(&["a", "b"]);
+        cache.insert("big".to_string(), 9, 4);
+        assert!(cache.get("big").is_none());
+        assert_eq!(cache.get("a"), Some(&0));
+        assert_eq!(cache.weight, 2);
+        // Replacing with an oversized value drops the old one
+        cache.insert("a".to_string(), 9, 4);
+        assert!(cache.get("a").is_none());
+        assert_eq!(cache.weight, 1);
+    }
+
+    #[test]
+    fn replacing_key_updates_weight() {
+        let mut cache = cache(&["a", "b"]);
+        cache.insert("a".to_string(), 5, 2);
+        assert_eq!(cache.weight, 3);
+        assert_eq!(cache.get("a"), Some(&5));
+        assert_eq!(cache.get("b"), Some(&1));
+        // Replaced entry is the most recent, so it outlives b
+        cache.insert("a".to_string(), 6, 3);
+        assert!(cache.get("b").is_none());
+        assert_eq!(cache.get("a"), Some(&6));
+        assert_eq!(cache.weight, 3);
+    }
+}
@@ -0,0 +1,897 @@
+//! HTTP client and catalog endpoints
+
+use std::error::Error;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, Arc};
+use std::time::{Duration, Instant};
+use reqwest::blocking::{Client, ClientBuilder, RequestBuilder};
+use reqwest::StatusCode;
+use reqwest::header::{HeaderMap, HeaderValue, CONTENT_TYPE, RETRY_AFTER};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use crate::models::*;
+use crate::error::AppleMusicError;
+use crate::diagnostics;
+use crate::cache::LruCache;
+use crate::ttml::{self, KaraokeLyrics};
+
+const URL: &'static str = "https://amp-api.music.apple.com/v1/catalog";
+/// User library and account endpoints
+pub(crate) const ME_URL: &'static str = "https://amp-api.music.apple.com/v1/me";
+/// Extended song attributes
+pub(crate) const SONG_EXTEND: &'static str = "artistUrl,audioVariants,moods,activities";
+/// Song relationships
+pub(crate) const SONG_INCLUDE: &'static str = "artists,albums,composers,genres";
+/// Mapped song attributes, requested with `fields[songs]` when unmapped ones aren't needed
+const SONG_FIELDS: &'static str = "albumName,artistName,artwork,audioLocale,audioTraits,composerName,contentRating,discNumber,durationInMillis,genreNames,hasLyrics,hasTimeSyncedLyrics,isAppleDigitalMaster,isrc,name,playParams,previews,releaseDate,trackNumber,url,attribution,workName,movementName,movementNumber,movementCount";
+/// Mapped attributes of related albums in search results
+const ALBUM_FIELDS: &'static str = "url,releaseDate,name,artistName,artistUrl,artwork,recordLabel,trackCount,upc,isMasteredForItunes,isCompilation,isSingle";
+/// Max songs per search request
+pub(crate) const SEARCH_LIMIT: usize = 50;
+/// Max songs per `songs?ids=` request
+const ALBUM_SONGS_CHUNK: usize = 100;
+/// Max ISRCs per `songs?filter[isrc]=` request
+const ISRC_CHUNK: usize = 25;
+/// Max page size of recently played tracks
+const RECENTLY_PLAYED_PAGE: usize = 30;
+/// Max page size of heavy rotation
+const HEAVY_ROTATION_PAGE: usize = 10;
+/// Artwork download attempts
+const ARTWORK_RETRIES: u32 = 3;
+/// Default max cached albums, artists and tracklist songs, per cache
+const CACHE_ENTRIES: usize = 5000;
+/// Default max bytes of artwork kept in memory
+const ARTWORK_CACHE_BYTES: usize = 128 * 1024 * 1024;
+/// Retries of rate limited (429) requests, waiting `Retry-After` or exponentially
+const RATE_LIMIT_RETRIES: u32 = 5;
+/// Identifying user agent for third-party APIs (MusicBrainz requires one)
+const THIRD_PARTY_USER_AGENT: &'static str = concat!("onetagger-apple-music/", env!("CARGO_PKG_VERSION"), " ( https://github.com/Marekkon5/onetagger-apple-music )");
+
+/// Stage of work reported to the progress callback
+#[derive(Debug, Clone, PartialEq)]
+pub enum Progress {
+    /// Started matching a file (plugin only)
+    Matching { path: PathBuf },
+    Searching { query: String },
+    FetchingAlbum { album_id: String },
+    FetchingLyrics { song_id: String },
+    /// Got 429, waiting before the next attempt
+    RateLimited { attempt: u32, wait: Duration },
+}
+
+/// Optional parts of search results, disabled ones are left out of the request to shrink responses
+#[derive(Debug, Clone)]
+pub struct SearchExtras {
+    /// Composers relationship of songs
+    pub composers: bool,
+    /// Record labels relationship of albums
+    pub record_labels: bool,
+    /// Editorial moods and activities
+    pub editorial: bool,
+    /// Audio variants (Dolby Atmos, lossless)
+    pub audio_variants: bool,
+    /// Unmapped attributes in `attributes.extra`, otherwise only mapped ones are requested
+    pub all_attributes: bool,
+}
+
+impl Default for SearchExtras {
+    fn default() -> Self {
+        SearchExtras { composers: true, record_labels: true, editorial: true, audio_variants: true, all_attributes: true }
+    }
+}
+
+/// Progress callback, shared between clones
+pub type ProgressCallback = Arc<dyn Fn(&Progress) + Send + Sync>;
+
+/// Client options for [`AppleMusic::with_options`]
+///
+/// ```ignore
+/// let options = AppleMusicOptions::new().language("de_DE").storefront("de").timeout(Duration::from_secs(10));
+/// let am = AppleMusic::with_options(token, options)?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct AppleMusicOptions {
+    pub(crate) language: String,
+    pub(crate) storefront: Option<String>,
+    pub(crate) artwork_size: u64,
+    pub(crate) artwork_format: ArtworkFormat,
+    pub(crate) timeout: Option<Duration>,
+    pub(crate) connect_timeout: Option<Duration>,
+    pub(crate) artwork_cache_dir: Option<PathBuf>,
+    pub(crate) rate_limit_retries: u32,
+    pub(crate) artwork_retries: u32,
+    pub(crate) lyric_highlights: bool,
+    pub(crate) search_extras: SearchExtras,
+    pub(crate) cache_entries: usize,
+    pub(crate) artwork_cache_bytes: usize,
+}
+
+impl AppleMusicOptions {
+    /// Default options
+    pub fn new() -> AppleMusicOptions {
+        AppleMusicOptions::default()
+    }
+
+    /// Language of metadata (`en_GB`, `de_DE`, ...)
+    pub fn language(mut self, language: &str) -> Self {
+        self.language = language.to_string();
+        self
+    }
+
+    /// Storefront (`us`, `gb`, ...) instead of the account's
+    pub fn storefront(mut self, storefront: &str) -> Self {
+        self.storefront = Some(storefront.trim().to_lowercase());
+        self
+    }
+
+    /// Size and format used by [`AppleMusic::artwork`]
+    pub fn artwork(mut self, size: u64, format: ArtworkFormat) -> Self {
+        self.artwork_size = size;
+        self.artwork_format = format;
+        self
+    }
+
+    /// Total timeout of a request
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Connection timeout
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Keep downloaded artwork in folder between runs
+    pub fn artwork_cache_dir(mut self, path: impl AsRef<Path>) -> Self {
+        self.artwork_cache_dir = Some(path.as_ref().to_owned());
+        self
+    }
+
+    /// Retries of rate limited (429) requests and failed artwork downloads
+    pub fn retries(mut self, rate_limit: u32, artwork: u32) -> Self {
+        self.rate_limit_retries = rate_limit;
+        self.artwork_retries = artwork.max(1);
+        self
+    }
+
+    /// Request lyric snippets with search results
+    pub fn lyric_highlights(mut self, enabled: bool) -> Self {
+        self.lyric_highlights = enabled;
+        self
+    }
+
+    /// Max entries of each metadata cache (albums, artists, songs of tracklists) and bytes of cached artwork,
+    /// least recently used ones are evicted
+    pub fn cache_limits(mut self, entries: usize, artwork_bytes: usize) -> Self {
+        self.cache_entries = entries;
+        self.artwork_cache_bytes = artwork_bytes;
+        self
+    }
+
+    /// Parts of search results to request, everything by default
+    pub fn search_extras(mut self, extras: SearchExtras) -> Self {
+        self.search_extras = extras;
+        self
+    }
+}
+
+impl Default for AppleMusicOptions {
+    fn default() -> Self {
+        AppleMusicOptions {
+            language: "en_GB".to_string(),
+            storefront: None,
+            artwork_size: 1400,
+            artwork_format: ArtworkFormat::Jpg,
+            timeout: None,
+            connect_timeout: None,
+            artwork_cache_dir: None,
+            rate_limit_retries: RATE_LIMIT_RETRIES,
+            artwork_retries: ARTWORK_RETRIES,
+            lyric_highlights: true,
+            search_extras: SearchExtras::default(),
+            cache_entries: CACHE_ENTRIES,
+            artwork_cache_bytes: ARTWORK_CACHE_BYTES,
+        }
+    }
+}
+
+/// Apple Music catalog client, clones share the token and caches
+#[derive(Clone)]
+pub struct AppleMusic {
+    pub(crate) client: Client,
+    /// Client for hosts other than Apple, without the token and Apple headers
+    pub(crate) third_party: Client,
+    pub(crate) access_token: Arc<Mutex<Option<String>>>,
+    pub(crate) catalog: Arc<Mutex<Option<String>>>,
+    pub(crate) language: String,
+    #[cfg(feature = "onetagger")]
+    pub(crate) config: crate::tagger::AppleMusicConfig,
+    /// Full albums by album ID, shared by all worker threads
+    pub(crate) albums: Arc<Mutex<LruCache<Arc<ItemMeta<AlbumAttributes>>>>>,
+    /// Locks of albums being fetched, so concurrent tracks of one album wait for a single request
+    pub(crate) album_fetches: Arc<Mutex<HashMap<String, Arc<Mutex<()>>>>>,
+    pub(crate) artists: Arc<Mutex<LruCache<ItemMeta<ArtistAttributes>>>>,
+    /// Matched album ID by folder and album tag
+    #[cfg(feature = "onetagger")]
+    pub(crate) album_context: Arc<Mutex<HashMap<String, String>>>,
+    /// Already written folder files (covers, NFO)
+    #[cfg(feature = "onetagger")]
+    pub(crate) covers: Arc<Mutex<std::collections::HashSet<std::path::PathBuf>>>,
+    /// Downloaded artwork by URL
+    pub(crate) artwork_cache: Arc<Mutex<LruCache<Arc<Vec<u8>>>>>,
+    /// Full songs of album / playlist by album / playlist ID, bounded by song count
+    pub(crate) album_songs: Arc<Mutex<LruCache<Vec<ItemMeta<SongAttributes>>>>>,
+    /// Number of tracks currently being matched
+    #[cfg(feature = "onetagger")]
+    pub(crate) workers: Arc<(Mutex<usize>, std::sync::Condvar)>,
+    /// Number of matched tracks currently being enriched (album, lyrics, ...)
+    #[cfg(feature = "onetagger")]
+    pub(crate) enrich_workers: Arc<(Mutex<usize>, std::sync::Condvar)>,
+    /// Songs resolved in folder batches by `storefront:id:` / `storefront:isrc:` key
+    #[cfg(feature = "onetagger")]
+    pub(crate) known_songs: Arc<Mutex<LruCache<Vec<ItemMeta<SongAttributes>>>>>,
+    /// Folders whose track IDs and ISRCs were resolved, locked while resolving
+    #[cfg(feature = "onetagger")]
+    pub(crate) prefetched: Arc<Mutex<HashMap<PathBuf, Arc<Mutex<bool>>>>>,
+    /// Diagnostics of tracks matched in this run
+    #[cfg(feature = "onetagger")]
+    pub(crate) diagnostics: Arc<Mutex<Vec<diagnostics::TrackDiagnostics>>>,
+    /// ID of library playlist matched songs are added to in this run
+    #[cfg(feature = "onetagger")]
+    pub(crate) playlist: Arc<Mutex<Option<String>>>,
+    /// Flattened metadata of tracks for the sidecar CSV of this run
+    #[cfg(feature = "onetagger")]
+    pub(crate) metadata_rows: Arc<Mutex<Vec<std::collections::BTreeMap<String, String>>>>,
+    pub(crate) progress: Option<ProgressCallback>,
+    pub(crate) options: AppleMusicOptions,
+}
+
+impl AppleMusic {
+    /// Create new instance with default options
+    pub fn new(media_user_token: &str) -> Result<AppleMusic, Box<dyn Error>> {
+        AppleMusic::with_options(media_user_token, AppleMusicOptions::default())
+    }
+
+    /// Create new instance, fails if the token isn't a valid header value
+    pub fn with_options(media_user_token: &str, options: AppleMusicOptions) -> Result<AppleMusic, Box<dyn Error>> {
+        let mut headers = HeaderMap::new();
+        let token = HeaderValue::from_str(media_user_token).map_err(|_| "Media User Token contains invalid characters")?;
+        headers.insert("Media-User-Token", token);
+        headers.insert("Content-Type", HeaderValue::from_static("application/json"));
+        headers.insert("Origin", HeaderValue::from_static("https://music.apple.com"));
+        headers.insert("Referer", HeaderValue::from_static("https://music.apple.com/"));
+
+        let mut client = ClientBuilder::new()
+            .user_agent("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/86.0.4240.183 Safari/537.36")
+            .default_headers(headers);
+        if let Some(timeout) = options.timeout {
+            client = client.timeout(timeout);
+        }
+        if let Some(timeout) = options.connect_timeout {
+            client = client.connect_timeout(timeout);
+        }
+        let mut third_party = ClientBuilder::new().user_agent(THIRD_PARTY_USER_AGENT);
+        if let Some(timeout) = options.timeout {
+            third_party = third_party.timeout(timeout);
+        }
+
+        let mut am = AppleMusic {
+            access_token: Arc::new(Mutex::new(None)),
+            catalog: Arc::new(Mutex::new(options.storefront.clone())),
+            client: client.build()?,
+            third_party: third_party.build()?,
+            language: String::new(),
+            #[cfg(feature = "onetagger")]
+            config: crate::tagger::AppleMusicConfig { media_user_token: media_user_token.to_string(), ..Default::default() },
+            albums: Arc::new(Mutex::new(LruCache::new(options.cache_entries))),
+            album_fetches: Arc::new(Mutex::new(HashMap::new())),
+            artists: Arc::new(Mutex::new(LruCache::new(options.cache_entries))),
+            #[cfg(feature = "onetagger")]
+            album_context: Arc::new(Mutex::new(HashMap::new())),
+            #[cfg(feature = "onetagger")]
+            covers: Arc::new(Mutex::new(Default::default())),
+            artwork_cache: Arc::new(Mutex::new(LruCache::new(options.artwork_cache_bytes))),
+            album_songs: Arc::new(Mutex::new(LruCache::new(options.cache_entries))),
+            #[cfg(feature = "onetagger")]
+            workers: Arc::new((Mutex::new(0), Default::default())),
+            #[cfg(feature = "onetagger")]
+            enrich_workers: Arc::new((Mutex::new(0), Default::default())),
+            #[cfg(feature = "onetagger")]
+            known_songs: Arc::new(Mutex::new(LruCache::new(options.cache_entries))),
+            #[cfg(feature = "onetagger")]
+            prefetched: Arc::new(Mutex::new(HashMap::new())),
+            #[cfg(feature = "onetagger")]
+            diagnostics: Arc::new(Mutex::new(vec![])),
+            #[cfg(feature = "onetagger")]
+            playlist: Arc::new(Mutex::new(None)),
+            #[cfg(feature = "onetagger")]
+            metadata_rows: Arc::new(Mutex::new(vec![])),
+            progress: None,
+            options: options.clone(),
+        };
+        am.set_language(&options.language);
+        Ok(am)
+    }
+
+    /// Clone using a different storefront, token and caches are shared
+    pub fn with_storefront(&self, storefront: &str) -> AppleMusic {
+        let mut am = self.clone();
+        am.catalog = Arc::new(Mutex::new(Some(storefront.to_string())));
+        am
+    }
+
+    /// Set language of metadata (`en_GB`, `de_DE`, ...)
+    pub fn set_language(&mut self, language: &str) {
+        self.language = language.trim().replace('-', "_");
+    }
+
+    /// Report progress stages to callback, e.g. to forward them into a channel:
+    /// `am.on_progress(move |p| { tx.send(p.clone()).ok(); })`
+    pub fn on_progress(&mut self, callback: impl Fn(&Progress) + Send + Sync + 'static) {
+        self.progress = Some(Arc::new(callback));
+    }
+
+    /// Call the progress callback if set
+    pub(crate) fn report(&self, progress: Progress) {
+        if let Some(callback) = self.progress.as_ref() {
+            callback(&progress);
+        }
+    }
+
+    /// Do a GET request to catalog endpoint
+    pub(crate) fn get<O: DeserializeOwned>(&self, path: &str, query: &[(&str, &str)]) -> Result<O, Box<dyn Error>> {
+        let catalog = match self.storefront() {
+            Some(catalog) => catalog,
+            None => {
+                self.fetch_token()?;
+                self.storefront().ok_or("Unable to get storefront")?
+            }
+        };
+        self.get_url(&format!("{URL}/{catalog}/{path}"), query)
+    }
+
+    /// Do a GET request to full URL
+    pub(crate) fn get_url<O: DeserializeOwned>(&self, url: &str, query: &[(&str, &str)]) -> Result<O, Box<dyn Error>> {
+        let mut query = query.to_vec();
+        if !query.iter().any(|(k, _)| *k == "l") {
+            query.push(("l", &self.language));
+        }
+        let body = self.send(url, || self.client.get(url).query(&query))?;
+        Ok(serde_json::from_str(&body).map_err(|e| AppleMusicError::SchemaChanged(format!("{url}: {e}")))?)
+    }
+
+    /// Do a POST request to full URL, `Value::Null` if the response has no body
+    pub(crate) fn post_url(&self, url: &str, query: &[(&str, &str)], body: Option<&Value>) -> Result<Value, Box<dyn Error>> {
+        let body = self.send(url, || match body {
+            Some(body) => self.client.post(url).query(query).json(body),
+            None => self.client.post(url).query(query),
+        })?;
+        if body.trim().is_empty() {
+            return Ok(Value::Null);
+        }
+        Ok(serde_json::from_str(&body).map_err(|e| AppleMusicError::SchemaChanged(format!("{url}: {e}")))?)
+    }
+
+    /// Send authorized request, retrying rate limited ones. Returns the response body
+    fn send(&self, url: &str, request: impl Fn() -> RequestBuilder) -> Result<String, Box<dyn Error>> {
+        // Get token
+        if self.access_token.lock().unwrap().is_none() {
+            self.fetch_token()?;
+        }
+        let token = self.access_token.lock().unwrap().as_ref().unwrap().to_string();
+        debug!("{url}");
+        let start = Instant::now();
+        let mut attempt = 0;
+        let response = loop {
+            let response = request()
+                .bearer_auth(&token)
+                .send()
+                .map_err(AppleMusicError::from)?;
+            if response.status() != StatusCode::TOO_MANY_REQUESTS {
+                break response;
+            }
+            let retry_after = response.headers().get(RETRY_AFTER)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.parse().ok())
+                .map(Duration::from_secs);
+            if attempt >= self.options.rate_limit_retries {
+                return Err(AppleMusicError::RateLimited { retry_after }.into());
+            }
+            attempt += 1;
+            let wait = retry_after.unwrap_or(Duration::from_secs(2u64.pow(attempt)));
+            warn!("Rate limited, retrying in {}s", wait.as_secs());
+            self.report(Progress::RateLimited { attempt, wait });
+            std::thread::sleep(wait);
+        };
+        match response.status() {
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => return Err(AppleMusicError::AuthExpired.into()),
+            StatusCode::NOT_FOUND => return Err(AppleMusicError::NotFound.into()),
+            status if !status.is_success() => return Err(AppleMusicError::Network(format!("HTTP {status} for {url}")).into()),
+            _ => {}
+        }
+        let body = response.text().map_err(AppleMusicError::from)?;
+        diagnostics::record_request(start.elapsed());
+        Ok(body)
+    }
+
+    /// Add catalog songs to the user's library
+    pub fn add_to_library(&self, song_ids: &[&str]) -> Result<(), Box<dyn Error>> {
+        self.post_url(&format!("{ME_URL}/library"), &[("ids[songs]", &song_ids.join(","))], None)?;
+        Ok(())
+    }
+
+    /// User's rating of catalog or library (`i.`) song, 1 = loved, -1 = disliked, `None` if unrated
+    pub fn song_rating(&self, song_id: &str) -> Result<Option<i8>, Box<dyn Error>> {
+        let kind = match song_id.starts_with("i.") {
+            true => "library-songs",
+            false => "songs"
+        };
+        let r: Result<DataResponse<RatingAttributes>, _> = self.get_url(&format!("{ME_URL}/ratings/{kind}"), &[("ids", song_id)]);
+        match r {
+            Ok(r) => Ok(r.data.into_iter().find(|r| r.id == song_id).map(|r| r.attributes.value)),
+            Err(e) if matches!(e.downcast_ref(), Some(AppleMusicError::NotFound)) => Ok(None),
+            Err(e) => Err(e)
+        }
+    }
+
+    /// Library version of catalog or library (`i.`) song, `None` if it isn't in the library.
+    /// Library only attributes (`playCount`, `lastPlayedDate`) are in `attributes.extra` when exposed.
+    pub fn library_song(&self, song_id: &str) -> Result<Option<ItemMeta<SongAttributes>>, Box<dyn Error>> {
+        let query = [("extend", "playCount,lastPlayedDate")];
+        let r: Result<DataResponse<SongAttributes>, _> = match song_id.starts_with("i.") {
+            true => self.get_url(&format!("{ME_URL}/library/songs/{song_id}"), &query),
+            false => self.get(&format!("songs/{song_id}/library"), &query)
+        };
+        match r {
+            Ok(r) => Ok(r.data.into_iter().next()),
+            Err(e) if matches!(e.downcast_ref(), Some(AppleMusicError::NotFound)) => Ok(None),
+            Err(e) => Err(e)
+        }
+    }
+
+    /// Up to `limit` recently played songs, most recent first. Catalog or library songs, music videos are skipped
+    pub fn recently_played(&self, limit: usize) -> Result<Vec<ItemMeta<SongAttributes>>, Box<dyn Error>> {
+        let songs: Vec<ItemMeta<SongAttributes>> = self.get_paged(&format!("{ME_URL}/recent/played/tracks"), limit, RECENTLY_PLAYED_PAGE)?;
+        Ok(songs.into_iter().filter(|s| !s.href.contains("/music-videos/")).collect())
+    }
+
+    /// Personal recommendations (Listen Now rows), contents are in `relationships.contents`
+    pub fn recommendations(&self) -> Result<Vec<ItemMeta<RecommendationAttributes>>, Box<dyn Error>> {
+        let r: DataResponse<RecommendationAttributes> = self.get_url(&format!("{ME_URL}/recommendations"), &[])?;
+        Ok(r.data)
+    }
+
+    /// Up to `limit` albums, playlists and stations the user recently listens to a lot
+    pub fn heavy_rotation(&self, limit: usize) -> Result<Vec<ItemMeta<ResourceAttributes>>, Box<dyn Error>> {
+        self.get_paged(&format!("{ME_URL}/history/heavy-rotation"), limit, HEAVY_ROTATION_PAGE)
+    }
+
+    /// Get up to `limit` items from offset paginated endpoint
+    fn get_paged<A: DeserializeOwned>(&self, url: &str, limit: usize, page_size: usize) -> Result<Vec<ItemMeta<A>>, Box<dyn Error>> {
+        let mut items = vec![];
+        while items.len() < limit {
+            let page = (limit - items.len()).min(page_size).to_string();
+            let offset = items.len().to_string();
+            let r: DataResponse<A> = self.get_url(url, &[("limit", &page), ("offset", &offset)])?;
+            let last = r.next.is_none() || r.data.is_empty();
+            items.extend(r.data);
+            if last {
+                break;
+            }
+        }
+        Ok(items)
+    }
+
+    /// Playlists in the user's library
+    pub fn library_playlists(&self) -> Result<Vec<ItemMeta<LibraryPlaylistAttributes>>, Box<dyn Error>> {
+        let mut playlists = vec![];
+        loop {
+            let offset = playlists.len().to_string();
+            let r: DataResponse<LibraryPlaylistAttributes> = self.get_url(&format!("{ME_URL}/library/playlists"), &[("limit", "100"), ("offset", &offset)])?;
+            let last = r.next.is_none() || r.data.is_empty();
+            playlists.extend(r.data);
+            if last {
+                return Ok(playlists);
+            }
+        }
+    }
+
+    /// Create library playlist, returns its ID
+    pub fn create_playlist(&self, name: &str, description: &str) -> Result<String, Box<dyn Error>> {
+        let body = serde_json::json!({ "attributes": { "name": name, "description": description } });
+        let r = self.post_url(&format!("{ME_URL}/library/playlists"), &[], Some(&body))?;
+        Ok(r["data"][0]["id"].as_str().ok_or("Missing ID of created playlist")?.to_string())
+    }
+
+    /// Append catalog songs to library playlist
+    pub fn add_to_playlist(&self, playlist_id: &str, song_ids: &[&str]) -> Result<(), Box<dyn Error>> {
+        let data: Vec<Value> = song_ids.iter().map(|id| serde_json::json!({ "id": id, "type": "songs" })).collect();
+        self.post_url(&format!("{ME_URL}/library/playlists/{playlist_id}/tracks"), &[], Some(&serde_json::json!({ "data": data })))?;
+        Ok(())
+    }
+
+    /// Search songs in the user's library (including uploads), catalog versions are used where available
+    pub fn library_search(&self, query: &str) -> Result<Vec<ItemMeta<SongAttributes>>, Box<dyn Error>> {
+        self.report(Progress::Searching { query: query.to_string() });
+        diagnostics::record(|d| d.queries.push(format!("library: {query}")));
+        let r: LibrarySearchResponse = self.get_url(&format!("{ME_URL}/library/search"), &[
+            ("term", query),
+            ("types", "library-songs"),
+            ("include", "catalog"),
+            ("limit", "25"),
+        ])?;
+        Ok(r.results.library_songs.map(|r| r.data).unwrap_or_default().into_iter().map(catalog_song).collect())
+    }
+
+    /// Search for tracks
+    pub fn search(&self, query: &str) -> Result<SearchResults, Box<dyn Error>> {
+        self.search_page(query, 0, SEARCH_LIMIT)
+    }
+
+    /// Search for tracks, `limit` results from `offset` (max 50)
+    pub fn search_page(&self, query: &str, offset: usize, limit: usize) -> Result<SearchResults, Box<dyn Error>> {
+        self.report(Progress::Searching { query: query.to_string() });
+        let storefront = self.storefront().unwrap_or_default();
+        diagnostics::record(|d| d.queries.push(format!("{storefront}: {query}")));
+        let extras = &self.options.search_extras;
+        let extend: Vec<&str> = [("artistUrl", true), ("audioVariants", extras.audio_variants), ("moods", extras.editorial), ("activities", extras.editorial)]
+            .into_iter().filter(|(_, enabled)| *enabled).map(|(attribute, _)| attribute).collect();
+        let extend = extend.join(",");
+        let include_songs = if extras.composers { SONG_INCLUDE } else { "artists,albums,genres" };
+        let include_albums = if extras.record_labels { "artists,record-labels" } else { "artists" };
+        let song_fields = format!("{SONG_FIELDS},{extend}");
+        let offset = offset.to_string();
+        let limit = limit.clamp(1, SEARCH_LIMIT).to_string();
+        let mut params = vec![
+            ("groups", "song"),
+            ("art[url]", "c,f"),
+            ("extend", extend.as_str()),
+            ("include[songs]", include_songs),
+            ("include[albums]", include_albums),
+            ("offset", offset.as_str()),
+            ("term", query),
+            ("types", "songs"),
+            ("platform", "web"),
+            ("limit", limit.as_str()),
+            ("omit[resource]", "autos"),
+        ];
+        if self.options.lyric_highlights {
+            params.push(("with", "lyrics,lyricHighlights"));
+        }
+        if !extras.all_attributes {
+            params.push(("fields[songs]", song_fields.as_str()));
+            params.push(("fields[albums]", ALBUM_FIELDS));
+        }
+        let r: SearchResultsResponse = self.get("search", &params)?;
+        Ok(r.results)
+    }
+
+    /// Search for artists
+    pub fn search_artists(&self, query: &str) -> Result<Vec<ItemMeta<ArtistAttributes>>, Box<dyn Error>> {
+        let r: ArtistSearchResponse = self.get("search", &[
+            ("groups", "artist"),
+            ("term", query),
+            ("types", "artists"),
+            ("limit", "10"),
+        ])?;
+        Ok(r.results.artist.map(|a| a.data).unwrap_or_default())
+    }
+
+    /// Get song by ID
+    pub fn song(&self, song_id: &str) -> Result<ItemMeta<SongAttributes>, Box<dyn Error>> {
+        self.song_in_language(song_id, &self.language)
+    }
+
+    /// Get song by ID with metadata in given language
+    pub fn song_in_language(&self, song_id: &str, language: &str) -> Result<ItemMeta<SongAttributes>, Box<dyn Error>> {
+        let r: DataResponse<SongAttributes> = self.get(&format!("songs/{song_id}"), &[
+            ("extend", SONG_EXTEND),
+            ("include", SONG_INCLUDE),
+            ("l", language),
+        ])?;
+        Ok(r.data.into_iter().next().ok_or("Song not found")?)
+    }
+
+    /// Download the 30s AAC preview of a song
+    pub fn download_preview(&self, song: &ItemMeta<SongAttributes>) -> Result<Vec<u8>, Box<dyn Error>> {
+        let url = song.attributes.previews.first().ok_or("Song has no preview")?.url.as_str();
+        Ok(self.client.get(url).send()?.error_for_status()?.bytes()?.to_vec())
+    }
+
+    /// Download HLS stream (highest bandwidth variant) into single fMP4 file
+    pub fn download_hls(&self, url: &str, path: &Path) -> Result<(), Box<dyn Error>> {
+        let resolve = |base: &str, uri: &str| match uri.starts_with("http") {
+            true => uri.to_string(),
+            false => format!("{}/{uri}", base.rsplit_once('/').map(|(b, _)| b).unwrap_or(base)),
+        };
+        // Pick variant
+        let master = self.client.get(url).send()?.error_for_status()?.text()?;
+        let mut best: Option<(u64, String)> = None;
+        let mut lines = master.lines();
+        while let Some(line) = lines.next() {
+            if let Some(attributes) = line.strip_prefix("#EXT-X-STREAM-INF:") {
+                let bandwidth = attributes.split(',')
+                    .find_map(|a| a.strip_prefix("BANDWIDTH="))
+                    .map(|b| b.parse().ok()).flatten()
+                    .unwrap_or(0);
+                if let Some(uri) = lines.next() {
+                    if best.as_ref().map(|(b, _)| bandwidth > *b).unwrap_or(true) {
+                        best = Some((bandwidth, resolve(url, uri.trim())));
+                    }
+                }
+            }
+        }
+        let variant = best.map(|(_, u)| u).unwrap_or(url.to_string());
+        // Init section + segments
+        let playlist = self.client.get(&variant).send()?.error_for_status()?.text()?;
+        let mut data = vec![];
+        for line in playlist.lines().map(|l| l.trim()).filter(|l| !l.is_empty()) {
+            let uri = match line.strip_prefix("#EXT-X-MAP:") {
+                Some(map) => map.split("URI=\"").nth(1).map(|u| u.split('"').next()).flatten().ok_or("Invalid EXT-X-MAP")?,
+                None if line.starts_with('#') => continue,
+                None => line,
+            };
+            data.extend_from_slice(&self.client.get(resolve(&variant, uri)).send()?.error_for_status()?.bytes()?);
+        }
+        debug!("Writing HLS stream to: {}", path.display());
+        std::fs::write(path, data)?;
+        Ok(())
+    }
+
+    /// Get full album, cached per album ID
+    pub fn album(&self, album_id: &str) -> Result<ItemMeta<AlbumAttributes>, Box<dyn Error>> {
+        Ok(self.shared_album(album_id)?.as_ref().clone())
+    }
+
+    /// Get full album from the shared cache, fetched once even when requested by multiple threads at once
+    pub(crate) fn shared_album(&self, album_id: &str) -> Result<Arc<ItemMeta<AlbumAttributes>>, Box<dyn Error>> {
+        if let Some(album) = self.albums.lock().unwrap().get(album_id) {
+            return Ok(album.clone());
+        }
+        let fetch = self.album_fetches.lock().unwrap().entry(album_id.to_string()).or_default().clone();
+        let _fetch = fetch.lock().unwrap();
+        // Fetched by other thread while waiting
+        if let Some(album) = self.albums.lock().unwrap().get(album_id) {
+            return Ok(album.clone());
+        }
+        self.report(Progress::FetchingAlbum { album_id: album_id.to_string() });
+        let r: DataResponse<AlbumAttributes> = self.get(&format!("albums/{album_id}"), &[
+            ("include", "artists,record-labels"),
+            ("extend", "editorialVideo,editorialArtwork"),
+        ])?;
+        let album = Arc::new(r.data.into_iter().next().ok_or("Album not found")?);
+        self.albums.lock().unwrap().insert(album_id.to_string(), album.clone(), 1);
+        self.album_fetches.lock().unwrap().remove(album_id);
+        Ok(album)
+    }
+
+    /// Get all songs of album with full attributes, cached per album ID
+    pub fn album_songs(&self, album_id: &str) -> Result<Vec<ItemMeta<SongAttributes>>, Box<dyn Error>> {
+        if let Some(songs) = self.album_songs.lock().unwrap().get(album_id) {
+            return Ok(songs.clone());
+        }
+        let album = self.shared_album(album_id)?;
+        // Tracks can also be music videos
+        let ids: Vec<String> = album.relationships.as_ref().map(|r| r.tracks.as_ref()).flatten()
+            .map(|t| t.data.iter().filter(|t| t.href.contains("/songs/")).map(|t| t.id.to_string()).collect())
+            .unwrap_or_default();
+        let songs = self.songs(&ids.iter().map(|id| id.as_str()).collect::<Vec<_>>())?;
+        self.album_songs.lock().unwrap().insert(album_id.to_string(), songs.clone(), songs.len());
+        Ok(songs)
+    }
+
+    /// Get songs of catalog (`pl.`) or library (`p.`) playlist by ID or URL in order, cached per playlist ID.
+    /// Library songs are replaced with their catalog version when available.
+    pub fn playlist_songs(&self, playlist: &str) -> Result<Vec<ItemMeta<SongAttributes>>, Box<dyn Error>> {
+        let id = playlist_id(playlist).ok_or("Invalid playlist URL or ID")?;
+        if let Some(songs) = self.album_songs.lock().unwrap().get(id) {
+            return Ok(songs.clone());
+        }
+        let library = id.starts_with("p.");
+        let mut songs = vec![];
+        loop {
+            let offset = songs.len().to_string();
+            let r: DataResponse<SongAttributes> = match library {
+                true => self.get_url(&format!("{ME_URL}/library/playlists/{id}/tracks"), &[
+                    ("include", "catalog"),
+                    ("limit", "100"),
+                    ("offset", &offset),
+                ])?,
+                false => self.get(&format!("playlists/{id}/tracks"), &[
+                    ("extend", SONG_EXTEND),
+                    ("include", SONG_INCLUDE),
+                    ("limit", "100"),
+                    ("offset", &offset),
+                ])?
+            };
+            let last = r.next.is_none() || r.data.is_empty();
+            songs.extend(r.data);
+            if last {
+                break;
+            }
+        }
+        // Tracks can also be music videos
+        let songs: Vec<_> = songs.into_iter().filter(|s| !s.href.contains("/music-videos/")).map(catalog_song).collect();
+        self.album_songs.lock().unwrap().insert(id.to_string(), songs.clone(), songs.len());
+        Ok(songs)
+    }
+
+    /// Get artist with extended info (bio, origin), cached per artist ID
+    pub fn get_artist(&self, artist_id: &str) -> Result<ItemMeta<ArtistAttributes>, Box<dyn Error>> {
+        if let Some(artist) = self.artists.lock().unwrap().get(artist_id) {
+            return Ok(artist.clone());
+        }
+        let r: DataResponse<ArtistAttributes> = self.get(&format!("artists/{artist_id}"), &[
+            ("extend", "artistBio,bornOrFormed,origin"),
+        ])?;
+        let artist = r.data.into_iter().next().ok_or("Artist not found")?;
+        self.artists.lock().unwrap().insert(artist_id.to_string(), artist.clone(), 1);
+        Ok(artist)
+    }
+
+    /// Download artwork in the size and format from options
+    pub fn artwork(&self, artwork: &AppleMusicArtwork) -> Result<Vec<u8>, Box<dyn Error>> {
+        self.download_artwork(artwork, self.options.artwork_size, self.options.artwork_format)
+    }
+
+    /// Download artwork scaled to fit `size` (keeping aspect ratio), retried according to options
+    pub fn download_artwork(&self, artwork: &AppleMusicArtwork, size: u64, format: ArtworkFormat) -> Result<Vec<u8>, Box<dyn Error>> {
+        let (width, height) = artwork.fit(size);
+        let url = artwork.url_for(width, height, format, ArtworkCrop::Square);
+        let mut error: Box<dyn Error> = "Artwork not downloaded".into();
+        for attempt in 0..self.options.artwork_retries {
+            if attempt > 0 {
+                std::thread::sleep(Duration::from_millis(500 * attempt as u64));
+            }
+            match self.download_image(&url) {
+                Ok(data) => return Ok(data),
+                Err(e) => {
+                    warn!("Artwork download failed (attempt {}): {e}", attempt + 1);
+                    error = e;
+                }
+            }
+        }
+        Err(error)
+    }
+
+    /// Download image, checking the content type. Cached per URL, and on disk if `artwork_cache_dir` is set
+    pub(crate) fn download_image(&self, url: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+        if let Some(data) = self.cached_artwork(url) {
+            return Ok(data.to_vec());
+        }
+        let cache_file = self.options.artwork_cache_dir.as_ref().map(|dir| {
+            let mut hasher = std::collections::hash_map::DefaultHasher::new();
+            url.hash(&mut hasher);
+            dir.join(format!("{:016x}", hasher.finish()))
+        });
+        if let Some(data) = cache_file.as_ref().map(|f| std::fs::read(f).ok()).flatten() {
+            self.artwork_cache.lock().unwrap().insert(url.to_string(), Arc::new(data.clone()), data.len());
+            return Ok(data);
+        }
+        let response = self.client.get(url).send()?.error_for_status()?;
+        let content_type = response.headers().get(CONTENT_TYPE).map(|c| c.to_str().ok()).flatten().unwrap_or_default().to_string();
+        if !content_type.starts_with("image/") {
+            return Err(format!("Invalid artwork content type: {content_type}").into());
+        }
+        let data = response.bytes()?.to_vec();
+        if let Some(file) = cache_file {
+            if let Err(e) = file.parent().map(std::fs::create_dir_all).unwrap_or(Ok(())).and_then(|_| std::fs::write(&file, &data)) {
+                warn!("Failed writing artwork cache {}: {e}", file.display());
+            }
+        }
+        self.artwork_cache.lock().unwrap().insert(url.to_string(), Arc::new(data.clone()), data.len());
+        Ok(data)
+    }
+
+    /// Get artwork downloaded during this run, unless evicted
+    pub fn cached_artwork(&self, url: &str) -> Option<Arc<Vec<u8>>> {
+        self.artwork_cache.lock().unwrap().get(url).cloned()
+    }
+
+    /// Drop all cached metadata and artwork, shared with clones. The on disk artwork cache is kept
+    pub fn clear_caches(&self) {
+        self.clear_metadata_caches();
+        self.artwork_cache.lock().unwrap().clear();
+    }
+
+    /// Drop cached albums, artists and tracklists
+    pub(crate) fn clear_metadata_caches(&self) {
+        self.albums.lock().unwrap().clear();
+        self.album_fetches.lock().unwrap().clear();
+        self.artists.lock().unwrap().clear();
+        self.album_songs.lock().unwrap().clear();
+        #[cfg(feature = "onetagger")]
+        {
+            self.known_songs.lock().unwrap().clear();
+            self.prefetched.lock().unwrap().clear();
+        }
+    }
+
+    /// Get song credits grouped by category
+    pub fn credits(&self, song_id: &str) -> Result<Vec<CreditCategory>, Box<dyn Error>> {
+        let r: CreditsResponse = self.get(&format!("songs/{song_id}/credits"), &[])?;
+        Ok(r.data)
+    }
+
+    /// Get songs by ISRC
+    pub fn songs_by_isrc(&self, isrc: &str) -> Result<Vec<ItemMeta<SongAttributes>>, Box<dyn Error>> {
+        let r: DataResponse<SongAttributes> = self.get("songs", &[
+            ("filter[isrc]", isrc),
+            ("extend", SONG_EXTEND),
+            ("include", SONG_INCLUDE),
+        ])?;
+        Ok(r.data)
+    }
+
+    /// Get songs by IDs with one request per 100 IDs, unknown IDs are left out
+    pub fn songs(&self, ids: &[&str]) -> Result<Vec<ItemMeta<SongAttributes>>, Box<dyn Error>> {
+        let mut songs = vec![];
+        for chunk in ids.chunks(ALBUM_SONGS_CHUNK) {
+            let r: DataResponse<SongAttributes> = self.get("songs", &[
+                ("ids", &chunk.join(",")),
+                ("extend", SONG_EXTEND),
+                ("include", SONG_INCLUDE),
+            ])?;
+            songs.extend(r.data);
+        }
+        Ok(songs)
+    }
+
+    /// Get songs of multiple ISRCs with one request per 25 ISRCs
+    pub fn songs_by_isrcs(&self, isrcs: &[&str]) -> Result<Vec<ItemMeta<SongAttributes>>, Box<dyn Error>> {
+        let mut songs = vec![];
+        for chunk in isrcs.chunks(ISRC_CHUNK) {
+            let r: DataResponse<SongAttributes> = self.get("songs", &[
+                ("filter[isrc]", &chunk.join(",")),
+                ("extend", SONG_EXTEND),
+                ("include", SONG_INCLUDE),
+            ])?;
+            songs.extend(r.data);
+        }
+        Ok(songs)
+    }
+
+    /// Get equivalent versions (explicit / clean) of a song
+    pub fn equivalents(&self, song_id: &str) -> Result<Vec<ItemMeta<SongAttributes>>, Box<dyn Error>> {
+        let r: DataResponse<SongAttributes> = self.get("songs", &[
+            ("filter[equivalents]", song_id),
+            ("extend", SONG_EXTEND),
+            ("include", SONG_INCLUDE),
+        ])?;
+        Ok(r.data)
+    }
+
+    /// Get the lyrics with per-word timing, agents and background vocals
+    pub fn karaoke_lyrics(&self, song_id: &str) -> Result<KaraokeLyrics, Box<dyn Error>> {
+        self.karaoke_lyrics_in_language(song_id, &self.language)
+    }
+
+    /// Get karaoke lyrics in a different language than metadata
+    pub fn karaoke_lyrics_in_language(&self, song_id: &str, language: &str) -> Result<KaraokeLyrics, Box<dyn Error>> {
+        self.report(Progress::FetchingLyrics { song_id: song_id.to_string() });
+        let lyrics: Value = self.get(&format!("songs/{song_id}/lyrics"), &[("l", language)])?;
+        let ttml = lyrics["data"][0]["attributes"]["ttml"].as_str().ok_or("Missing TTML")?;
+        Ok(ttml::parse_ttml(ttml, language)?)
+    }
+}
+
+/// Catalog version of library song, if it has one
+fn catalog_song(song: ItemMeta<SongAttributes>) -> ItemMeta<SongAttributes> {
+    match song.relationships.as_ref().map(|r| r.catalog.as_ref()).flatten().map(|c| c.data.first()).flatten() {
+        Some(catalog) => catalog.clone(),
+        None => song
+    }
+}
+
+/// Playlist ID from `music.apple.com` URL or ID
+fn playlist_id(playlist: &str) -> Option<&str> {
+    let id = playlist.trim().split(['?', '#']).next()?.trim_end_matches('/').rsplit('/').next()?;
+    match id.starts_with("pl.") || id.starts_with("p.") {
+        true => Some(id),
+        false => None
+    }
+}
@@ -0,0 +1,66 @@
+//! Deezer / Spotify track IDs by ISRC, for converting playlists later without matching again
+
+use std::error::Error;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use serde::Deserialize;
+use serde_json::Value;
+use crate::AppleMusic;
+
+const DEEZER_URL: &'static str = "https://api.deezer.com";
+const SPOTIFY_URL: &'static str = "https://api.spotify.com/v1";
+const SPOTIFY_TOKEN_URL: &'static str = "https://accounts.spotify.com/api/token";
+/// Refresh Spotify token this long before it expires
+const SPOTIFY_TOKEN_MARGIN: Duration = Duration::from_secs(60);
+
+/// Spotify client credentials token with expiry, shared by all threads
+static SPOTIFY_TOKEN: Mutex<Option<(String, Instant)>> = Mutex::new(None);
+
+#[derive(Debug, Clone, Deserialize)]
+struct SpotifyToken {
+    access_token: String,
+    expires_in: u64,
+}
+
+impl AppleMusic {
+    /// Deezer track ID by ISRC, `None` if not on Deezer
+    pub(crate) fn deezer_id(&self, isrc: &str) -> Result<Option<String>, Box<dyn Error>> {
+        let r: Value = self.third_party.get(format!("{DEEZER_URL}/track/isrc:{isrc}")).send()?.error_for_status()?.json()?;
+        // Missing tracks are returned as error objects with status 200
+        if r.get("error").is_some() {
+            return Ok(None);
+        }
+        Ok(r["id"].as_u64().map(|id| id.to_string()))
+    }
+
+    /// Spotify track ID by ISRC, needs Spotify app credentials. `None` if not on Spotify
+    pub(crate) fn spotify_id(&self, isrc: &str, client_id: &str, client_secret: &str) -> Result<Option<String>, Box<dyn Error>> {
+        let token = self.spotify_token(client_id, client_secret)?;
+        let r: Value = self.third_party.get(format!("{SPOTIFY_URL}/search"))
+            .query(&[("q", format!("isrc:{isrc}").as_str()), ("type", "track"), ("limit", "1")])
+            .bearer_auth(token)
+            .send()?
+            .error_for_status()?
+            .json()?;
+        Ok(r["tracks"]["items"][0]["id"].as_str().map(String::from))
+    }
+
+    /// Cached client credentials token
+    fn spotify_token(&self, client_id: &str, client_secret: &str) -> Result<String, Box<dyn Error>> {
+        let mut cached = SPOTIFY_TOKEN.lock().unwrap();
+        if let Some((token, expires)) = cached.as_ref() {
+            if Instant::now() < *expires {
+                return Ok(token.to_string());
+            }
+        }
+        let r: SpotifyToken = self.third_party.post(SPOTIFY_TOKEN_URL)
+            .basic_auth(client_id, Some(client_secret))
+            .form(&[("grant_type", "client_credentials")])
+            .send()?
+            .error_for_status()?
+            .json()?;
+        let expires = Instant::now() + Duration::from_secs(r.expires_in).saturating_sub(SPOTIFY_TOKEN_MARGIN);
+        *cached = Some((r.access_token.to_string(), expires));
+        Ok(r.access_token)
+    }
+}
@@ -0,0 +1,70 @@
+//! Per-track diagnostics collected on the matching thread
+
+use std::cell::RefCell;
+use std::path::PathBuf;
+use std::time::Duration;
+use serde::Serialize;
+
+thread_local! {
+    /// Diagnostics of the track currently matched on this thread
+    static CURRENT: RefCell<Option<TrackDiagnostics>> = RefCell::new(None);
+}
+
+/// What happened while matching a single file
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrackDiagnostics {
+    pub path: PathBuf,
+    /// Search queries in order, prefixed with storefront
+    pub queries: Vec<String>,
+    /// Candidates pooled from all queries
+    pub candidates: usize,
+    pub song_id: Option<String>,
+    pub accuracy: Option<f64>,
+    /// Accuracy with bonuses / penalties
+    pub score: Option<f64>,
+    pub api_requests: u32,
+    pub api_time_ms: u64,
+    pub error: Option<String>,
+}
+
+/// Start collecting for file on this thread
+#[cfg(feature = "onetagger")]
+pub(crate) fn start(path: &std::path::Path) {
+    CURRENT.with(|c| *c.borrow_mut() = Some(TrackDiagnostics { path: path.to_owned(), ..Default::default() }));
+}
+
+/// Stop collecting and take the diagnostics
+#[cfg(feature = "onetagger")]
+pub(crate) fn finish() -> Option<TrackDiagnostics> {
+    CURRENT.with(|c| c.borrow_mut().take())
+}
+
+/// Add requests and queries collected on a helper thread to the current track
+#[cfg(feature = "onetagger")]
+pub(crate) fn merge(other: Option<TrackDiagnostics>) {
+    if let Some(other) = other {
+        record(|d| {
+            d.queries.extend(other.queries);
+            d.api_requests += other.api_requests;
+            d.api_time_ms += other.api_time_ms;
+        });
+    }
+}
+
+/// Update diagnostics of the current track, no-op if not collecting
+pub(crate) fn record(f: impl FnOnce(&mut TrackDiagnostics)) {
+    CURRENT.with(|c| {
+        if let Some(diagnostics) = c.borrow_mut().as_mut() {
+            f(diagnostics);
+        }
+    });
+}
+
+/// Count API request and its duration
+pub(crate) fn record_request(time: Duration) {
+    record(|d| {
+        d.api_requests += 1;
+        d.api_time_ms += time.as_millis() as u64;
+    });
+}
@@ -0,0 +1,49 @@
+//! Error categories for retry / skip decisions
+
+use std::error::Error;
+use std::fmt;
+use std::time::Duration;
+
+/// Categorized failure, returned boxed and can be recovered with `error.downcast_ref::<AppleMusicError>()`
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum AppleMusicError {
+    /// Still rate limited after all retries, pause the batch before retrying
+    RateLimited { retry_after: Option<Duration> },
+    /// Media User Token invalid or expired, re-authentication needed
+    AuthExpired,
+    /// Resource doesn't exist in the storefront, skip
+    NotFound,
+    /// Connection failure or unexpected HTTP status, retry later
+    Network(String),
+    /// Response or web page doesn't have the expected format, plugin update needed
+    SchemaChanged(String),
+}
+
+impl AppleMusicError {
+    /// Whether retrying the same request later can succeed
+    pub fn retryable(&self) -> bool {
+        matches!(self, AppleMusicError::RateLimited { .. } | AppleMusicError::Network(_))
+    }
+}
+
+impl fmt::Display for AppleMusicError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AppleMusicError::RateLimited { retry_after: Some(wait) } => write!(f, "Rate limited by Apple Music, retry in {}s", wait.as_secs()),
+            AppleMusicError::RateLimited { retry_after: None } => write!(f, "Rate limited by Apple Music"),
+            AppleMusicError::AuthExpired => write!(f, "Media User Token is invalid or expired: log in to music.apple.com again and copy a fresh `media-user-token` cookie"),
+            AppleMusicError::NotFound => write!(f, "Not found on Apple Music"),
+            AppleMusicError::Network(e) => write!(f, "Apple Music request failed: {e}"),
+            AppleMusicError::SchemaChanged(e) => write!(f, "Unexpected Apple Music response, the plugin might need an update: {e}"),
+        }
+    }
+}
+
+impl Error for AppleMusicError {}
+
+impl From<reqwest::Error> for AppleMusicError {
+    fn from(e: reqwest::Error) -> Self {
+        AppleMusicError::Network(e.to_string())
+    }
+}
@@ -0,0 +1,131 @@
+//! C API of the client, declared in `include/onetagger_apple_music.h`
+//!
+//! Returned strings are owned by the caller and freed with `am_string_free`.
+//! On failure `NULL` is returned and `am_last_error` has the message, panics are caught and reported the same way.
+//! Pointer arguments must be `NULL` or valid, strings NUL terminated UTF-8.
+#![allow(clippy::missing_safety_doc)]
+
+use std::cell::RefCell;
+use std::error::Error;
+use std::ffi::{CStr, CString};
+use std::os::raw::c_char;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::ptr;
+use crate::AppleMusic;
+
+thread_local! {
+    /// Message of the last failed call on this thread
+    static LAST_ERROR: RefCell<Option<CString>> = RefCell::new(None);
+}
+
+/// Create client with Media User Token, `NULL` if it is invalid. Free with `am_client_free`
+#[no_mangle]
+pub unsafe extern "C" fn am_client_new(media_user_token: *const c_char) -> *mut AppleMusic {
+    guard(ptr::null_mut(), || Ok(Box::into_raw(Box::new(AppleMusic::new(to_str(media_user_token)?)?))))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn am_client_free(client: *mut AppleMusic) {
+    guard((), || {
+        if !client.is_null() {
+            drop(Box::from_raw(client));
+        }
+        Ok(())
+    })
+}
+
+/// Search results as JSON
+#[no_mangle]
+pub unsafe extern "C" fn am_search(client: *const AppleMusic, query: *const c_char) -> *mut c_char {
+    call(client, query, |am, query| Ok(serde_json::to_string(&am.search(query)?)?))
+}
+
+/// Song with album / artist relationships as JSON
+#[no_mangle]
+pub unsafe extern "C" fn am_song_json(client: *const AppleMusic, song_id: *const c_char) -> *mut c_char {
+    call(client, song_id, |am, id| Ok(serde_json::to_string(&am.song(id)?)?))
+}
+
+/// Lyrics as LRC, unsynced lines without timestamps
+#[no_mangle]
+pub unsafe extern "C" fn am_lyrics_lrc(client: *const AppleMusic, song_id: *const c_char) -> *mut c_char {
+    call(client, song_id, |am, id| Ok(am.karaoke_lyrics(id)?.to_lrc()))
+}
+
+/// Error message of the last failed call on this thread, `NULL` if none. Owned by the library
+#[no_mangle]
+pub extern "C" fn am_last_error() -> *const c_char {
+    catch_unwind(|| LAST_ERROR.with(|e| e.borrow().as_ref().map(|e| e.as_ptr()).unwrap_or(ptr::null()))).unwrap_or(ptr::null())
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn am_string_free(s: *mut c_char) {
+    guard((), || {
+        if !s.is_null() {
+            drop(CString::from_raw(s));
+        }
+        Ok(())
+    })
+}
+
+/// Run client method with string argument and return the output as C string
+unsafe fn call(client: *const AppleMusic, arg: *const c_char, f: impl FnOnce(&AppleMusic, &str) -> Result<String, Box<dyn Error>>) -> *mut c_char {
+    guard(ptr::null_mut(), || {
+        let am = client.as_ref().ok_or("Client is NULL")?;
+        Ok(CString::new(f(am, to_str(arg)?)?)?.into_raw())
+    })
+}
+
+/// Run body of export, errors and panics are stored for `am_last_error` and `fallback` is returned
+fn guard<T>(fallback: T, f: impl FnOnce() -> Result<T, Box<dyn Error>>) -> T {
+    match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(out)) => out,
+        Ok(Err(e)) => {
+            set_error(e);
+            fallback
+        },
+        Err(panic) => {
+            let message = panic.downcast_ref::<&str>().map(|s| s.to_string())
+                .or_else(|| panic.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "Unknown panic".to_string());
+            set_error(format!("Panicked: {message}").into());
+            fallback
+        }
+    }
+}
+
+unsafe fn to_str<'a>(s: *const c_char) -> Result<&'a str, Box<dyn Error>> {
+    if s.is_null() {
+        return Err("Argument is NULL".into());
+    }
+    Ok(CStr::from_ptr(s).to_str()?)
+}
+
+fn set_error(e: Box<dyn Error>) {
+    let message = CString::new(e.to_string().replace('\0', "")).ok();
+    LAST_ERROR.with(|l| *l.borrow_mut() = message);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn last_error() -> String {
+        unsafe { CStr::from_ptr(am_last_error()) }.to_string_lossy().to_string()
+    }
+
+    #[test]
+    fn error_is_reported() {
+        assert!(unsafe { am_client_new(ptr::null()) }.is_null());
+        assert_eq!(last_error(), "Argument is NULL");
+        assert!(unsafe { am_search(ptr::null(), ptr::null()) }.is_null());
+        assert_eq!(last_error(), "Client is NULL");
+    }
+
+    #[test]
+    fn panic_is_reported() {
+        let out = guard(ptr::null_mut::<c_char>(), || panic!("boom"));
+        assert!(out.is_null());
+        assert_eq!(last_error(), "Panicked: boom");
+    }
+}
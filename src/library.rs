@@ -0,0 +1,155 @@
+//! Comparison of songs matched in a run with the user's library
+
+use std::collections::HashSet;
+use std::error::Error;
+use std::path::PathBuf;
+use serde::{Serialize, Deserialize};
+use crate::AppleMusic;
+use crate::client::ME_URL;
+use crate::models::*;
+
+/// Max page size of library songs
+const LIBRARY_SONGS_PAGE: usize = 100;
+
+/// Local file matched to catalog song
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LocalTrack {
+    pub path: PathBuf,
+    pub song_id: String,
+}
+
+/// Song in the user's library
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LibraryTrack {
+    /// Library (`i.`) ID
+    pub id: String,
+    pub catalog_id: Option<String>,
+    pub artist: String,
+    pub title: String,
+    pub album: String,
+}
+
+/// Local tracks not in the library and library songs without local track
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LibraryDiff {
+    pub missing_in_library: Vec<LocalTrack>,
+    pub missing_locally: Vec<LibraryTrack>,
+}
+
+impl LibraryDiff {
+    /// Match local tracks to library songs by library (`i.`) or catalog ID
+    fn new(library: &[LibraryTrack], local: &[LocalTrack]) -> LibraryDiff {
+        let library_ids: HashSet<&str> = library.iter()
+            .flat_map(|s| [Some(s.id.as_str()), s.catalog_id.as_deref()])
+            .flatten()
+            .collect();
+        let local_ids: HashSet<&str> = local.iter().map(|t| t.song_id.as_str()).collect();
+        LibraryDiff {
+            missing_in_library: local.iter().filter(|t| !library_ids.contains(t.song_id.as_str())).cloned().collect(),
+            missing_locally: library.iter()
+                .filter(|s| !local_ids.contains(s.id.as_str()) && !s.catalog_id.as_deref().map(|id| local_ids.contains(id)).unwrap_or(false))
+                .cloned()
+                .collect(),
+        }
+    }
+
+    /// Render as CSV with `status,path,song_id,artist,title,album` columns
+    pub fn to_csv(&self) -> String {
+        let mut out = String::from("status,path,song_id,artist,title,album\n");
+        for track in &self.missing_in_library {
+            out.push_str(&csv_row(&["missing_in_library", &track.path.to_string_lossy(), &track.song_id, "", "", ""]));
+        }
+        for track in &self.missing_locally {
+            let id = track.catalog_id.as_deref().unwrap_or(&track.id);
+            out.push_str(&csv_row(&["missing_locally", "", id, &track.artist, &track.title, &track.album]));
+        }
+        out
+    }
+}
+
+impl AppleMusic {
+    /// All songs in the user's library
+    pub fn library_songs(&self) -> Result<Vec<LibraryTrack>, Box<dyn Error>> {
+        let mut songs = vec![];
+        loop {
+            let offset = songs.len().to_string();
+            let r: DataResponse<SongAttributes> = self.get_url(&format!("{ME_URL}/library/songs"), &[
+                ("limit", &LIBRARY_SONGS_PAGE.to_string()),
+                ("offset", &offset),
+            ])?;
+            let last = r.next.is_none() || r.data.is_empty();
+            songs.extend(r.data.into_iter().map(|song| LibraryTrack {
+                catalog_id: song.attributes.play_params.as_ref().map(|p| p["catalogId"].as_str()).flatten().map(String::from),
+                artist: song.attributes.artist_name,
+                title: song.attributes.name,
+                album: song.attributes.album_name,
+                id: song.id,
+            }));
+            if last {
+                return Ok(songs);
+            }
+        }
+    }
+
+    /// Compare local tracks matched to catalog songs (e.g. from the diagnostics report) with the library
+    pub fn library_diff(&self, local: &[LocalTrack]) -> Result<LibraryDiff, Box<dyn Error>> {
+        Ok(LibraryDiff::new(&self.library_songs()?, local))
+    }
+}
+
+/// Quote CSV fields with separators, quotes or newlines
+pub(crate) fn csv_row(fields: &[&str]) -> String {
+    let fields: Vec<String> = fields.iter().map(|f| match f.contains([',', '"', '\n']) {
+        true => format!("\"{}\"", f.replace('"', "\"\"")),
+        false => f.to_string()
+    }).collect();
+    format!("{}\n", fields.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn library_track(id: &str, catalog_id: Option<&str>, title: &str) -> LibraryTrack {
+        LibraryTrack {
+            id: id.to_string(),
+            catalog_id: catalog_id.map(String::from),
+            artist: "Daft Punk".to_string(),
+            title: title.to_string(),
+            album: "Random Access Memories".to_string(),
+        }
+    }
+
+    fn local_track(path: &str, song_id: &str) -> LocalTrack {
+        LocalTrack { path: PathBuf::from(path), song_id: song_id.to_string() }
+    }
+
+    #[test]
+    fn csv_row_quoting() {
+        assert_eq!(csv_row(&["a", "b c", ""]), "a,b c,\n");
+        assert_eq!(csv_row(&["Earth, Wind & Fire", "12\" Mix", "line\nbreak"]), "\"Earth, Wind & Fire\",\"12\"\" Mix\",\"line\nbreak\"\n");
+    }
+
+    #[test]
+    fn diff_by_library_and_catalog_id() {
+        let library = [
+            library_track("i.AAA", Some("617154366"), "Get Lucky"),
+            library_track("i.BBB", None, "Upload"),
+            library_track("i.CCC", Some("617154367"), "Lose Yourself to Dance"),
+        ];
+        let local = [
+            local_track("Get Lucky.flac", "617154366"),
+            local_track("Upload.mp3", "i.BBB"),
+            local_track("Contact.flac", "617154373"),
+        ];
+        let diff = LibraryDiff::new(&library, &local);
+        assert_eq!(diff.missing_in_library.iter().map(|t| t.song_id.as_str()).collect::<Vec<_>>(), vec!["617154373"]);
+        assert_eq!(diff.missing_locally.iter().map(|t| t.id.as_str()).collect::<Vec<_>>(), vec!["i.CCC"]);
+        assert_eq!(diff.to_csv(), "status,path,song_id,artist,title,album\n\
+            missing_in_library,Contact.flac,617154373,,,\n\
+            missing_locally,,617154367,Daft Punk,Lose Yourself to Dance,Random Access Memories\n");
+    }
+}
@@ -0,0 +1,1042 @@
+//! Search candidate filtering and ranking against the file info
+
+use std::error::Error;
+use std::sync::OnceLock;
+use chrono::NaiveDate;
+use image::imageops::FilterType;
+use serde::Serialize;
+use onetagger_tagger::{Track, TaggerConfig, AudioFileInfo, MatchingUtils, MultipleMatchesSort};
+use crate::AppleMusic;
+use crate::client::SEARCH_LIMIT;
+use crate::models::*;
+use crate::tagger::MismatchMode;
+
+/// Score penalty for `MismatchMode::Penalize`
+const MISMATCH_PENALTY: f64 = 0.1;
+/// Score bonus for candidates from the album matched for previous tracks in folder
+const ALBUM_CONTEXT_BONUS: f64 = 0.25;
+/// Max duration difference in ms confirming a match in strict mode
+const STRICT_DURATION_DIFFERENCE: u64 = 2000;
+/// Max artwork hash distance before the match is penalized
+const ARTWORK_MAX_DISTANCE: u32 = 16;
+/// Accuracy penalty for artwork mismatch
+const ARTWORK_PENALTY: f64 = 0.2;
+/// Number of artist search results considered aliases of file's artist
+const ARTIST_ALIAS_RESULTS: usize = 3;
+/// Score penalty for titles with `penalized_patterns`
+const PATTERN_PENALTY: f64 = 0.2;
+/// Duration difference in ms with full `duration_weight` penalty
+const DURATION_WEIGHT_RANGE: f64 = 30_000.0;
+/// Weight of folder name similarity for files without album tag
+const FOLDER_ALBUM_WEIGHT: f64 = 0.1;
+/// Max duration difference in ms for album mode track number match
+const ALBUM_MODE_DURATION_DIFFERENCE: u64 = 5000;
+/// Score bonus / penalty for lyric highlight match / mismatch
+const LYRICS_BONUS: f64 = 0.1;
+/// Results of the first search page, the rest is only requested if none of them matches
+const FIRST_SEARCH_LIMIT: usize = 10;
+/// Max files of a folder whose tags are read to resolve them in batches
+const PREFETCH_MAX_FILES: usize = 500;
+/// Extensions of files read when resolving a folder
+const AUDIO_EXTENSIONS: &[&str] = &["mp3", "flac", "m4a", "mp4", "aif", "aiff", "ogg", "opus", "wav"];
+/// Max artwork size in candidate summaries
+const SUMMARY_ARTWORK_SIZE: u64 = 200;
+/// Album tag keys (ID3, Vorbis, MP4)
+const ALBUM_TAGS: &[&str] = &["TALB", "ALBUM", "©alb"];
+/// Album artist tag keys (ID3, Vorbis, MP4)
+const ALBUM_ARTIST_TAGS: &[&str] = &["TPE2", "ALBUMARTIST", "aART"];
+/// Unsynced lyrics tag keys (ID3, Vorbis, MP4)
+const LYRICS_TAGS: &[&str] = &["USLT", "LYRICS", "UNSYNCEDLYRICS", "©lyr"];
+/// Language tag keys (ID3, Vorbis)
+const LANGUAGE_TAGS: &[&str] = &["TLAN", "LANGUAGE"];
+/// Apple track ID tag keys written by 1T
+const TRACK_ID_TAGS: &[&str] = &["APPLE_MUSIC_TRACK_ID", "apple_music_track_id"];
+/// ISRC tag keys (ID3, Vorbis / MP4)
+const ISRC_TAGS: &[&str] = &["TSRC", "ISRC"];
+/// Date of tagging, for `skip_tagged_days`
+pub(crate) const TAGGED_DATE_TAG: &str = "APPLE_TAGGED_DATE";
+/// Disc number tag keys (ID3, Vorbis, MP4)
+const DISC_TAGS: &[&str] = &["TPOS", "DISCNUMBER", "disk"];
+/// Year / date tag keys (ID3, Vorbis, MP4)
+const YEAR_TAGS: &[&str] = &["TDRC", "TYER", "DATE", "YEAR", "©day"];
+
+impl AppleMusic {
+    /// Fill missing artist from album artist tag, missing artist / title from filename template
+    pub(crate) fn complete_info(&self, info: &AudioFileInfo) -> AudioFileInfo {
+        let mut info = info.clone();
+        if info.artists.iter().all(|a| a.trim().is_empty()) {
+            info.artists = file_tag(&info, ALBUM_ARTIST_TAGS).map(|a| vec![a]).unwrap_or_default();
+        }
+        // Combined artist tag, primary artist is searched with, rest is verified
+        if info.artists.len() == 1 {
+            let artists = split_artists(&info.artists[0]);
+            if !artists.is_empty() {
+                info.artists = artists;
+            }
+        }
+        let missing_title = info.title.as_deref().map(|t| t.trim().is_empty()).unwrap_or(true);
+        if info.artists.is_empty() || missing_title {
+            let stem = info.path.file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+            let (artist, title) = match self.config.filename_regex.as_ref() {
+                Some(template) => parse_filename(template, &stem),
+                None => (None, None)
+            };
+            if let (Some(artist), true) = (artist, info.artists.is_empty()) {
+                info.artists = vec![artist];
+            }
+            if missing_title {
+                info.title = title.or(info.title);
+            }
+        }
+        info
+    }
+
+    /// IDs of top artist search results for file's artist
+    fn artist_alias_ids(&self, info: &AudioFileInfo) -> Vec<String> {
+        let artist = split_featured(info.artist().unwrap_or_default()).0;
+        match self.search_artists(&artist) {
+            Ok(artists) => artists.into_iter().take(ARTIST_ALIAS_RESULTS).map(|a| a.id).collect(),
+            Err(e) => {
+                warn!("Artist search failed: {e}");
+                vec![]
+            }
+        }
+    }
+
+    /// File has Apple track ID from previous run, written within `skip_tagged_days` if set
+    pub(crate) fn already_tagged(&self, info: &AudioFileInfo) -> bool {
+        if file_tag(info, TRACK_ID_TAGS).is_none() {
+            return false;
+        }
+        if self.config.skip_tagged_days == 0 {
+            return true;
+        }
+        file_tag(info, &[TAGGED_DATE_TAG])
+            .map(|d| NaiveDate::parse_from_str(&d, "%Y-%m-%d").ok()).flatten()
+            .map(|d| chrono::Local::now().date_naive().signed_duration_since(d).num_days() <= self.config.skip_tagged_days as i64)
+            .unwrap_or(false)
+    }
+
+    /// Key of album context, files in same folder with same album tag
+    pub(crate) fn album_context_key(info: &AudioFileInfo) -> String {
+        let folder = info.path.parent().map(|p| p.to_string_lossy().to_string()).unwrap_or_default();
+        format!("{folder}|{}", file_tag(info, ALBUM_TAGS).unwrap_or_default())
+    }
+
+    /// Search query for file, featured artists are stripped from artist
+    fn query(info: &AudioFileInfo) -> Result<String, Box<dyn Error>> {
+        let (artist, _) = split_featured(info.artist()?);
+        Ok(format!("{} {}", artist, info.title()?))
+    }
+
+    /// Search with all query shapes and pool the deduplicated results, with the queries which filled their page.
+    /// Title + album and title only are used with `multi_query`, artist is verified in matching.
+    fn search_candidates(&self, info: &AudioFileInfo, limit: usize) -> Result<(Vec<ItemMeta<SongAttributes>>, Vec<String>), Box<dyn Error>> {
+        let query = Self::query(info)?;
+        let mut songs = self.search_page(&query, 0, limit)?.song.data;
+        let mut full = vec![];
+        if songs.len() >= limit {
+            full.push(query);
+        }
+        if !self.config.multi_query {
+            return Ok((songs, full));
+        }
+        let title = info.title()?;
+        let mut queries = vec![];
+        if let Some(album) = file_tag(info, ALBUM_TAGS) {
+            queries.push(format!("{title} {album}"));
+        }
+        queries.push(title.to_string());
+        for query in queries {
+            match self.search_page(&query, 0, limit) {
+                Ok(results) => {
+                    if results.song.data.len() >= limit {
+                        full.push(query.to_string());
+                    }
+                    for song in results.song.data {
+                        if !songs.iter().any(|s| s.id == song.id) {
+                            songs.push(song);
+                        }
+                    }
+                },
+                Err(e) => warn!("Search for {query} failed: {e}"),
+            }
+        }
+        Ok((songs, full))
+    }
+
+    /// Find the best matching song for file
+    pub(crate) fn find_match(&self, info: &AudioFileInfo, config: &TaggerConfig) -> Result<Option<RankedCandidate>, Box<dyn Error>> {
+        let track_id = file_tag(info, TRACK_ID_TAGS).filter(|id| config.match_by_id && catalog_id(id));
+        let isrc = info.isrc.as_ref().map(|i| i.trim()).filter(|i| !i.is_empty());
+        if track_id.is_some() || isrc.is_some() {
+            self.prefetch_folder(info, config);
+        }
+
+        // Track ID from previous run
+        if let Some(id) = track_id {
+            match self.known_song(&id) {
+                Ok(Some(song)) => {
+                    debug!("Matched by track ID: {id}");
+                    return Ok(Some(RankedCandidate::new(1.0, song, vec![("track_id", 0.0)])));
+                },
+                Ok(None) => debug!("Track ID {id} not found"),
+                Err(e) => warn!("Track ID lookup failed: {e}"),
+            }
+        }
+
+        // Exact ISRC match
+        if let Some(isrc) = isrc {
+            match self.isrc_songs(isrc) {
+                Ok(songs) => {
+                    let mut songs: Vec<_> = songs.into_iter().filter(|s| s.attributes.play_params.is_some()).collect();
+                    if songs.len() == 1 {
+                        debug!("Matched by ISRC: {isrc}");
+                        return Ok(Some(RankedCandidate::new(1.0, songs.remove(0), vec![("isrc", 0.0)])));
+                    }
+                },
+                Err(e) => warn!("ISRC lookup failed: {e}"),
+            }
+        }
+
+        // Tracks of the given playlist
+        if !self.config.source_playlist.trim().is_empty() {
+            match self.playlist_songs(&self.config.source_playlist) {
+                Ok(songs) => if let Some(candidate) = self.match_in_playlist(info, config, songs) {
+                    return Ok(Some(candidate));
+                },
+                Err(e) => warn!("Failed getting playlist tracks: {e}"),
+            }
+        }
+
+        // Album of previous tracks in folder
+        if self.config.album_mode {
+            if let Some(candidate) = self.match_in_album(info, config) {
+                return Ok(Some(candidate));
+            }
+        }
+
+        // User library first, it has uploads which aren't in catalog
+        if self.config.library_search {
+            match self.library_search(&Self::query(info)?) {
+                Ok(songs) => if let Some(candidate) = self.select_candidate(info, config, songs) {
+                    debug!("Matched in library: {}", candidate.song.id);
+                    return Ok(Some(candidate));
+                },
+                Err(e) => warn!("Library search failed: {e}"),
+            }
+        }
+
+        let (songs, full) = self.search_candidates(info, FIRST_SEARCH_LIMIT)?;
+        crate::diagnostics::record(|d| d.candidates += songs.len());
+        let mut candidate = match self.select_candidate(info, config, songs) {
+            Some(candidate) => candidate,
+            // Rest of the results of queries with a full first page, rejected ones would be rejected again
+            None if !full.is_empty() => {
+                let mut songs: Vec<ItemMeta<SongAttributes>> = vec![];
+                for query in &full {
+                    for song in self.search_page(query, FIRST_SEARCH_LIMIT, SEARCH_LIMIT - FIRST_SEARCH_LIMIT)?.song.data {
+                        if !songs.iter().any(|s| s.id == song.id) {
+                            songs.push(song);
+                        }
+                    }
+                }
+                crate::diagnostics::record(|d| d.candidates += songs.len());
+                match self.select_candidate(info, config, songs) {
+                    Some(candidate) => candidate,
+                    None => return Ok(None),
+                }
+            },
+            None => return Ok(None),
+        };
+        candidate.song = self.preferred_edition(candidate.song);
+
+        // Compare with embedded artwork
+        if self.config.verify_artwork {
+            if let Some(distance) = self.artwork_distance(info, &candidate.song) {
+                debug!("Artwork hash distance: {distance}");
+                if distance > ARTWORK_MAX_DISTANCE {
+                    candidate.accuracy -= ARTWORK_PENALTY;
+                    candidate.score -= ARTWORK_PENALTY;
+                    candidate.adjustments.push(("artwork", -ARTWORK_PENALTY));
+                    if candidate.accuracy < config.strictness {
+                        debug!("Rejected by artwork: {}", candidate.report(info));
+                        return Ok(None);
+                    }
+                }
+            }
+        }
+
+        // Don't replace existing ISRC with a different one
+        if self.config.verify_isrc {
+            if let Some(isrc) = info.isrc.as_ref().map(|i| i.trim()).filter(|i| !i.is_empty()) {
+                if let Some(song_isrc) = candidate.song.attributes.isrc.as_ref().filter(|i| !i.is_empty()) {
+                    if !isrc.eq_ignore_ascii_case(song_isrc) {
+                        info!("Rejected match {}, ISRC {song_isrc} differs from file's {isrc}", candidate.song.id);
+                        return Ok(None);
+                    }
+                }
+            }
+        }
+        Ok(Some(candidate))
+    }
+
+    /// Resolve track IDs from previous runs and ISRCs of all files in the folder with batched requests, once per folder
+    fn prefetch_folder(&self, info: &AudioFileInfo, config: &TaggerConfig) {
+        let folder = match info.path.parent() {
+            Some(folder) => folder,
+            None => return,
+        };
+        // Other threads wait for the batch instead of doing their own requests
+        let lock = self.prefetched.lock().unwrap().entry(folder.to_path_buf()).or_default().clone();
+        let mut done = lock.lock().unwrap();
+        if *done {
+            return;
+        }
+        *done = true;
+        let files: Vec<_> = match std::fs::read_dir(folder) {
+            Ok(entries) => entries.filter_map(|e| e.ok()).map(|e| e.path())
+                .filter(|p| p.extension().map(|e| AUDIO_EXTENSIONS.contains(&e.to_string_lossy().to_lowercase().as_str())).unwrap_or(false))
+                .take(PREFETCH_MAX_FILES)
+                .collect(),
+            Err(_) => return,
+        };
+        let storefront = self.storefront().unwrap_or_default();
+        let (mut ids, mut isrcs) = (vec![], vec![]);
+        for path in files {
+            let tag = match onetagger_tag::Tag::load_file(&path, false) {
+                Ok(tag) => tag,
+                Err(_) => continue,
+            };
+            let first = |keys: &[&str]| keys.iter().filter_map(|k| tag.tag().get_raw(k)).flatten().map(|v| v.trim().to_string()).find(|v| !v.is_empty());
+            match (first(TRACK_ID_TAGS).filter(|id| config.match_by_id && catalog_id(id)), first(ISRC_TAGS)) {
+                (Some(id), _) => ids.push(id),
+                (None, Some(isrc)) => isrcs.push(isrc.to_uppercase()),
+                _ => {}
+            }
+        }
+        {
+            let mut known = self.known_songs.lock().unwrap();
+            ids.retain(|id| known.get(&format!("{storefront}:id:{id}")).is_none());
+            isrcs.retain(|isrc| known.get(&format!("{storefront}:isrc:{isrc}")).is_none());
+        }
+        ids.sort();
+        ids.dedup();
+        isrcs.sort();
+        isrcs.dedup();
+        if ids.is_empty() && isrcs.is_empty() {
+            return;
+        }
+        debug!("Resolving {} track IDs and {} ISRCs in {}", ids.len(), isrcs.len(), folder.display());
+        if !ids.is_empty() {
+            match self.songs(&ids.iter().map(|id| id.as_str()).collect::<Vec<_>>()) {
+                Ok(songs) => {
+                    let mut known = self.known_songs.lock().unwrap();
+                    for id in &ids {
+                        let found: Vec<_> = songs.iter().filter(|s| &s.id == id).cloned().collect();
+                        known.insert(format!("{storefront}:id:{id}"), found, 1);
+                    }
+                },
+                Err(e) => warn!("Batched track ID lookup failed: {e}"),
+            }
+        }
+        if !isrcs.is_empty() {
+            match self.songs_by_isrcs(&isrcs.iter().map(|isrc| isrc.as_str()).collect::<Vec<_>>()) {
+                Ok(songs) => {
+                    let mut known = self.known_songs.lock().unwrap();
+                    for isrc in &isrcs {
+                        let found: Vec<_> = songs.iter().filter(|s| s.attributes.isrc.as_ref().map(|i| i.eq_ignore_ascii_case(isrc)).unwrap_or(false)).cloned().collect();
+                        let weight = found.len().max(1);
+                        known.insert(format!("{storefront}:isrc:{isrc}"), found, weight);
+                    }
+                },
+                Err(e) => warn!("Batched ISRC lookup failed: {e}"),
+            }
+        }
+    }
+
+    /// Song by track ID, resolved with the folder or by its own request
+    fn known_song(&self, id: &str) -> Result<Option<ItemMeta<SongAttributes>>, Box<dyn Error>> {
+        let key = format!("{}:id:{id}", self.storefront().unwrap_or_default());
+        if let Some(songs) = self.known_songs.lock().unwrap().get(&key) {
+            return Ok(songs.first().cloned());
+        }
+        Ok(self.songs(&[id])?.into_iter().next())
+    }
+
+    /// Songs with ISRC, resolved with the folder or by their own request
+    fn isrc_songs(&self, isrc: &str) -> Result<Vec<ItemMeta<SongAttributes>>, Box<dyn Error>> {
+        let key = format!("{}:isrc:{}", self.storefront().unwrap_or_default(), isrc.to_uppercase());
+        if let Some(songs) = self.known_songs.lock().unwrap().get(&key) {
+            return Ok(songs.clone());
+        }
+        self.songs_by_isrc(isrc)
+    }
+
+    /// Match against tracklist of the album matched for previous tracks in the folder,
+    /// by disc / track number and duration first, then like search results
+    fn match_in_album(&self, info: &AudioFileInfo, config: &TaggerConfig) -> Option<RankedCandidate> {
+        let album_id = self.album_context.lock().unwrap().get(&Self::album_context_key(info)).cloned()?;
+        let songs = match self.album_songs(&album_id) {
+            Ok(songs) => songs,
+            Err(e) => {
+                warn!("Failed getting album tracks: {e}");
+                return None;
+            }
+        };
+        // Track number
+        let disc: Option<i32> = file_tag(info, DISC_TAGS).map(|d| d.split('/').next().map(|d| d.trim().parse().ok()).flatten()).flatten();
+        if let Some(track_number) = info.track_number {
+            let song = songs.iter().find(|s| {
+                s.attributes.track_number == track_number as i32
+                    && disc.map(|d| d == s.attributes.disc_number).unwrap_or(true)
+                    && info.duration.map(|d| (d.as_millis() as u64).abs_diff(s.attributes.duration_in_millis) <= ALBUM_MODE_DURATION_DIFFERENCE).unwrap_or(false)
+            });
+            if let Some(song) = song {
+                debug!("Matched by album track number: {}", song.id);
+                return Some(RankedCandidate::new(1.0, song.clone(), vec![("album_track", 0.0)]));
+            }
+        }
+        self.rank_candidates(info, config, songs).into_iter().next()
+    }
+
+    /// Match against playlist tracks, by position (track number) and duration first, then like search results
+    fn match_in_playlist(&self, info: &AudioFileInfo, config: &TaggerConfig, songs: Vec<ItemMeta<SongAttributes>>) -> Option<RankedCandidate> {
+        if let (Some(track_number), Some(duration)) = (info.track_number, info.duration) {
+            let song = (track_number as usize).checked_sub(1).map(|i| songs.get(i)).flatten()
+                .filter(|s| (duration.as_millis() as u64).abs_diff(s.attributes.duration_in_millis) <= ALBUM_MODE_DURATION_DIFFERENCE);
+            if let Some(song) = song {
+                debug!("Matched by playlist position: {}", song.id);
+                return Some(RankedCandidate::new(1.0, song.clone(), vec![("playlist_position", 0.0)]));
+            }
+        }
+        self.select_candidate(info, config, songs)
+    }
+
+    /// Perceptual hash distance (0 - 64) of embedded and candidate's artwork, `None` if unavailable
+    fn artwork_distance(&self, info: &AudioFileInfo, song: &ItemMeta<SongAttributes>) -> Option<u32> {
+        let tag = onetagger_tag::Tag::load_file(&info.path, false).ok()?;
+        let embedded = dhash(&tag.tag().get_art().into_iter().next()?.data)?;
+        let url = song.attributes.artwork.as_ref()?.url_for(64, 64, ArtworkFormat::Jpg, ArtworkCrop::Square);
+        let data = self.download_image(&url).ok()?;
+        Some((embedded ^ dhash(&data)?).count_ones())
+    }
+
+    /// Switch to the preferred explicit / clean equivalent if available
+    fn preferred_edition(&self, song: ItemMeta<SongAttributes>) -> ItemMeta<SongAttributes> {
+        let wanted = match self.config.explicit_preference.explicit() {
+            Some(wanted) => wanted,
+            None => return song,
+        };
+        if song.attributes.explicit() != Some(!wanted) {
+            return song;
+        }
+        match self.equivalents(&song.id) {
+            Ok(songs) => {
+                if let Some(equivalent) = songs.into_iter().find(|s| s.id != song.id && s.attributes.explicit() == Some(wanted)) {
+                    debug!("Using equivalent edition: {}", equivalent.id);
+                    return equivalent;
+                }
+            },
+            Err(e) => warn!("Failed getting equivalent edition: {e}"),
+        }
+        song
+    }
+
+    /// Pick the best candidate from search results
+    fn select_candidate(&self, info: &AudioFileInfo, config: &TaggerConfig, songs: Vec<ItemMeta<SongAttributes>>) -> Option<RankedCandidate> {
+        let songs = self.filter_candidates(info, songs);
+        let mut ranked = self.rank_candidates(info, config, songs).into_iter();
+        match self.config.strict_matching {
+            true => ranked.find(|c| Self::confirmed(info, &c.song)),
+            false => ranked.next()
+        }
+    }
+
+    /// Match is confirmed by ISRC or duration within `STRICT_DURATION_DIFFERENCE`
+    fn confirmed(info: &AudioFileInfo, song: &ItemMeta<SongAttributes>) -> bool {
+        if let Some(isrc) = info.isrc.as_ref() {
+            if song.attributes.isrc.as_ref().map(|i| isrc.trim().eq_ignore_ascii_case(i)).unwrap_or(false) {
+                return true;
+            }
+        }
+        info.duration
+            .map(|d| (d.as_millis() as u64).abs_diff(song.attributes.duration_in_millis) <= STRICT_DURATION_DIFFERENCE)
+            .unwrap_or(false)
+    }
+
+    /// Get top `n` matching candidates with scores, best first, without fetching any extra data.
+    /// Ties are ordered by 1T's `multiple_matches` setting, same as in `match_track`.
+    /// 1T's manual tagging can't show them yet, `AutotaggerSource::match_track` returns a single match.
+    pub fn match_candidates(&self, info: &AudioFileInfo, config: &TaggerConfig, n: usize) -> Result<Vec<MatchCandidate>, Box<dyn Error>> {
+        let songs = self.filter_candidates(info, self.search_candidates(info, SEARCH_LIMIT)?.0);
+        Ok(self.rank_candidates(info, config, songs).into_iter().take(n).map(|c| MatchCandidate {
+            score: c.score,
+            accuracy: c.accuracy,
+            report: c.report(info),
+            track: c.song.to_track(&self.config)
+        }).collect())
+    }
+
+    /// Top `n` candidates as light summaries with thumbnail, for manual picking in UI
+    pub fn candidate_summaries(&self, info: &AudioFileInfo, config: &TaggerConfig, n: usize) -> Result<Vec<CandidateSummary>, Box<dyn Error>> {
+        let songs = self.filter_candidates(info, self.search_candidates(info, SEARCH_LIMIT)?.0);
+        Ok(self.rank_candidates(info, config, songs).into_iter().take(n).map(|c| {
+            let artwork_url = c.song.attributes.artwork.as_ref().map(|a| {
+                let (width, height) = a.fit(SUMMARY_ARTWORK_SIZE);
+                a.url_for(width, height, ArtworkFormat::Jpg, ArtworkCrop::Square)
+            });
+            CandidateSummary {
+                year: c.song.attributes.release_date.as_ref().map(|d| d.get(0..4).map(|y| y.parse().ok()).flatten()).flatten(),
+                artist: c.song.artists().join(", "),
+                title: c.song.attributes.name,
+                album: c.song.attributes.album_name,
+                url: c.song.attributes.url,
+                id: c.song.id,
+                artwork_url,
+                score: c.score,
+                accuracy: c.accuracy,
+            }
+        }).collect())
+    }
+
+    /// Remove unwanted candidates and move preferred ones first
+    fn filter_candidates(&self, info: &AudioFileInfo, mut songs: Vec<ItemMeta<SongAttributes>>) -> Vec<ItemMeta<SongAttributes>> {
+        // Discard radio edits / extended mixes
+        if let (Some(duration), true) = (info.duration, self.config.max_duration_difference > 0) {
+            let max = self.config.max_duration_difference as u64 * 1000;
+            songs.retain(|s| (duration.as_millis() as u64).abs_diff(s.attributes.duration_in_millis) <= max);
+        }
+        // Drop karaoke / cover / tribute versions, unless the file is one
+        let file = format!("{} {} {}", info.artist().unwrap_or_default(), info.title().unwrap_or_default(), file_tag(info, ALBUM_TAGS).unwrap_or_default()).to_lowercase();
+        let blocked: Vec<String> = self.config.blocklist.split(',')
+            .map(|p| p.trim().to_lowercase())
+            .filter(|p| !p.is_empty() && !file.contains(p))
+            .collect();
+        if !blocked.is_empty() {
+            songs.retain(|s| {
+                let candidate = format!("{} {} {}", s.attributes.artist_name, s.attributes.name, s.attributes.album_name).to_lowercase();
+                !blocked.iter().any(|p| candidate.contains(p))
+            });
+        }
+        // Move preferred candidates first, so they win ties
+        if self.config.prefer_digital_masters {
+            songs.sort_by_key(|s| !s.digital_master());
+        }
+        if self.config.prefer_non_compilations {
+            songs.sort_by_key(|s| s.album().map(|a| a.compilation()).unwrap_or(false));
+        }
+        if let Some(wanted) = self.config.explicit_preference.explicit() {
+            songs.sort_by_key(|s| s.attributes.explicit() != Some(wanted));
+        }
+        if self.config.prefer_original_albums {
+            songs.sort_by_key(|s| s.release_rank());
+        }
+        // Configured or file's language
+        let locale = Some(self.config.audio_locale.trim().to_string())
+            .filter(|l| !l.is_empty())
+            .or_else(|| file_tag(info, LANGUAGE_TAGS));
+        if let Some(locale) = locale {
+            songs.sort_by_key(|s| !locale_matches(&locale, s.attributes.audio_locale.as_deref().unwrap_or_default()));
+        }
+        songs
+    }
+
+    /// Match each candidate separately and rank by adjusted score, best first.
+    /// Accuracy is left unadjusted, so strictness still applies.
+    fn rank_candidates(&self, info: &AudioFileInfo, config: &TaggerConfig, songs: Vec<ItemMeta<SongAttributes>>) -> Vec<RankedCandidate> {
+        let album = file_tag(info, ALBUM_TAGS);
+        let year: Option<i16> = file_tag(info, YEAR_TAGS).map(|y| y.get(0..4).map(|y| y.parse().ok()).flatten()).flatten();
+        let mut featured: Vec<String> = info.artists.iter().skip(1).cloned().collect();
+        featured.extend(split_featured(info.artist().unwrap_or_default()).1);
+        featured.extend(split_featured(info.title().unwrap_or_default()).1);
+        let context_album = match self.config.album_context {
+            true => self.album_context.lock().unwrap().get(&Self::album_context_key(info)).cloned(),
+            false => None
+        };
+        let lyrics = match self.config.lyrics_highlights {
+            true => file_tag(info, LYRICS_TAGS).map(|l| normalize_lyrics(&l)),
+            false => None
+        };
+        let file_live = is_live(info.title().unwrap_or_default()) || album.as_deref().map(is_live).unwrap_or(false);
+        let title = info.title().unwrap_or_default().to_lowercase();
+        let penalized: Vec<String> = self.config.penalized_patterns.split(',')
+            .map(|p| p.trim().to_lowercase())
+            .filter(|p| !p.is_empty() && !title.contains(p))
+            .collect();
+        let folder_album = match (self.config.folder_album, &album) {
+            (true, None) => info.path.parent().map(|p| p.file_name()).flatten().map(|f| clean_folder_name(&f.to_string_lossy())),
+            _ => None
+        };
+        let mut aliases: Option<Vec<String>> = None;
+        // Compare without superficial style differences
+        let mut normalized = info.clone();
+        normalized.title = normalized.title.as_deref().map(normalize_text);
+        normalized.artists = normalized.artists.iter().map(|a| normalize_text(a)).collect();
+        // `A / B`, `A, B` can also be a single artist (`AC/DC`), the split is only used if it matches better
+        let split = normalized.artists.first().map(|a| split_ambiguous(a)).flatten().map(|artists| {
+            let mut split = normalized.clone();
+            split.artists = artists.into_iter().chain(normalized.artists.iter().skip(1).cloned()).collect();
+            split
+        });
+        let mut ranked = vec![];
+        for song in songs {
+            let mut track = song.to_match_track(&self.config);
+            track.title = normalize_text(&track.title);
+            track.artists = track.artists.iter().map(|a| normalize_text(a)).collect();
+            let tracks = vec![track];
+            let mut adjustments = vec![];
+            let mut matched = MatchingUtils::match_track(&normalized, &tracks, config, true);
+            let mut featured = featured.clone();
+            if let Some(split) = &split {
+                if let Some(m) = MatchingUtils::match_track(split, &tracks, config, true) {
+                    if matched.as_ref().map(|(acc, _)| m.0 > *acc).unwrap_or(true) {
+                        featured.extend(split.artists.iter().skip(1).cloned());
+                        adjustments.push(("artist_split", 0.0));
+                        matched = Some(m);
+                    }
+                }
+            }
+            let (acc, track) = match matched {
+                Some(m) => m,
+                // Artist can be renamed / stylized, accept if it is one of the artist search results
+                None if self.config.artist_aliases => match MatchingUtils::match_track(&normalized, &tracks, config, false) {
+                    Some(m) => {
+                        let ids = aliases.get_or_insert_with(|| self.artist_alias_ids(info));
+                        if !song.artist_ids().iter().any(|id| ids.contains(id)) {
+                            continue;
+                        }
+                        adjustments.push(("artist_alias", 0.0));
+                        m
+                    },
+                    None => continue,
+                },
+                None => continue,
+            };
+            // Same album as previous tracks from the folder
+            if context_album.is_some() && song.album_id() == context_album {
+                adjustments.push(("album_context", ALBUM_CONTEXT_BONUS));
+            }
+            // Album similarity
+            if let (Some(album), true) = (&album, self.config.album_weight > 0) {
+                adjustments.push(("album", self.config.album_weight as f64 / 100.0 * similarity(album, track.album.as_deref().unwrap_or_default())));
+            }
+            // Folder name as album for files without album tag
+            if let Some(folder) = &folder_album {
+                adjustments.push(("folder_album", FOLDER_ALBUM_WEIGHT * similarity(folder, &song.attributes.album_name)));
+            }
+            // Closer duration wins, normalized to `DURATION_WEIGHT_RANGE`
+            if let (Some(duration), true) = (info.duration, self.config.duration_weight > 0) {
+                let delta = (duration.as_millis() as u64).abs_diff(song.attributes.duration_in_millis) as f64;
+                adjustments.push(("duration", -(self.config.duration_weight as f64 / 100.0) * (delta / DURATION_WEIGHT_RANGE).min(1.0)));
+            }
+            // Release year proximity, 10 years apart is neutral, further is penalized
+            if let (Some(year), Some(release_year), true) = (year, track.release_year, self.config.year_weight > 0) {
+                let proximity = (1.0 - (year - release_year).abs() as f64 / 10.0).max(-1.0);
+                adjustments.push(("year", self.config.year_weight as f64 / 100.0 * proximity));
+            }
+            // Lyric snippet from search has to be in file's lyrics
+            if let Some(lyrics) = &lyrics {
+                let highlights = song.lyric_highlights();
+                if !highlights.is_empty() {
+                    match highlights.iter().any(|h| lyrics.contains(&normalize_lyrics(h))) {
+                        true => adjustments.push(("lyrics", LYRICS_BONUS)),
+                        false => adjustments.push(("lyrics", -LYRICS_BONUS)),
+                    }
+                }
+            }
+            // Featured / additional artists have to be credited on the candidate and vice versa
+            if self.config.verify_featured {
+                let candidate_featured = split_featured(&song.attributes.name).1;
+                let mut candidate_artists = song.artists();
+                candidate_artists.extend(candidate_featured.iter().cloned());
+                let found = featured.iter().all(|f| candidate_artists.iter().any(|c| similarity(f, c) >= config.strictness));
+                let extra = candidate_featured.iter().any(|c| !featured.iter().any(|f| similarity(f, c) >= config.strictness));
+                if !found || extra {
+                    adjustments.push(("featured", -MISMATCH_PENALTY));
+                }
+            }
+            // Sped up / slowed versions unless the file is one
+            let name = song.attributes.name.to_lowercase();
+            if penalized.iter().any(|p| name.contains(p)) {
+                adjustments.push(("pattern", -PATTERN_PENALTY));
+            }
+            // Remix / edit version mismatch
+            if self.config.version_matching != MismatchMode::Disabled && version_tokens(info.title().unwrap_or_default()) != version_tokens(&song.attributes.name) {
+                match self.config.version_matching {
+                    MismatchMode::Penalize => adjustments.push(("version", -MISMATCH_PENALTY)),
+                    _ => continue,
+                }
+            }
+            // Live / studio mismatch
+            if (is_live(&song.attributes.name) || is_live(&song.attributes.album_name)) != file_live {
+                match self.config.live_versions {
+                    MismatchMode::Disabled => {},
+                    MismatchMode::Penalize => adjustments.push(("live", -MISMATCH_PENALTY)),
+                    MismatchMode::Exclude => continue,
+                }
+            }
+            let candidate = RankedCandidate::new(acc, song, adjustments);
+            debug!("Candidate {}", candidate.report(info));
+            ranked.push(candidate);
+        }
+        // Stable, so preferred candidates still win ties unless 1T's multiple matches sort is set
+        ranked.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(std::cmp::Ordering::Equal).then_with(|| release_order(
+            &config.multiple_matches,
+            a.song.attributes.release_date.as_deref(),
+            b.song.attributes.release_date.as_deref()
+        )));
+        ranked
+    }
+}
+
+/// Order of equally scored candidates by 1T's multiple matches setting, undated ones last
+fn release_order(sort: &MultipleMatchesSort, a: Option<&str>, b: Option<&str>) -> std::cmp::Ordering {
+    match (sort, a, b) {
+        (MultipleMatchesSort::Default, _, _) | (_, None, None) => std::cmp::Ordering::Equal,
+        (_, Some(_), None) => std::cmp::Ordering::Less,
+        (_, None, Some(_)) => std::cmp::Ordering::Greater,
+        (MultipleMatchesSort::Oldest, Some(a), Some(b)) => a.cmp(b),
+        (MultipleMatchesSort::Newest, Some(a), Some(b)) => b.cmp(a),
+    }
+}
+
+/// Candidate returned by `AppleMusic::match_candidates`
+#[derive(Debug, Clone)]
+pub struct MatchCandidate {
+    /// Score used for ranking (accuracy adjusted by album, live and version matching)
+    pub score: f64,
+    /// Track accuracy
+    pub accuracy: f64,
+    /// Score breakdown, see `RankedCandidate::report`
+    pub report: String,
+    pub track: Track,
+}
+
+/// Summary of candidate returned by `AppleMusic::candidate_summaries`
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CandidateSummary {
+    /// Apple Music song ID
+    pub id: String,
+    pub title: String,
+    pub artist: String,
+    pub album: String,
+    pub year: Option<i16>,
+    /// Small square artwork
+    pub artwork_url: Option<String>,
+    pub url: String,
+    pub score: f64,
+    pub accuracy: f64,
+}
+
+/// Scored search result with breakdown of the score
+#[derive(Debug, Clone)]
+pub(crate) struct RankedCandidate {
+    pub score: f64,
+    pub accuracy: f64,
+    pub song: ItemMeta<SongAttributes>,
+    /// Applied bonuses / penalties
+    pub adjustments: Vec<(&'static str, f64)>,
+}
+
+impl RankedCandidate {
+    /// Score is accuracy with adjustments applied
+    pub fn new(accuracy: f64, song: ItemMeta<SongAttributes>, adjustments: Vec<(&'static str, f64)>) -> RankedCandidate {
+        let score = accuracy + adjustments.iter().map(|(_, v)| v).sum::<f64>();
+        RankedCandidate { score, accuracy, song, adjustments }
+    }
+
+    /// Human readable breakdown, eg. `123: score=0.85 accuracy=0.95 title=0.95 artist=1.00 duration=+2.1s live=-0.10`
+    pub fn report(&self, info: &AudioFileInfo) -> String {
+        let title = similarity(info.title().unwrap_or_default(), &self.song.attributes.name);
+        let artist = self.song.artists().iter().map(|a| similarity(info.artist().unwrap_or_default(), a)).fold(0.0, f64::max);
+        let mut report = format!("{}: score={:.2} accuracy={:.2} title={title:.2} artist={artist:.2}", self.song.id, self.score, self.accuracy);
+        if let Some(duration) = info.duration {
+            let delta = self.song.attributes.duration_in_millis as f64 - duration.as_millis() as f64;
+            report.push_str(&format!(" duration={:+.1}s", delta / 1000.0));
+        }
+        for (name, value) in &self.adjustments {
+            report.push_str(&format!(" {name}={value:+.2}"));
+        }
+        report
+    }
+}
+
+/// Is live recording by title / album name
+fn is_live(name: &str) -> bool {
+    static LIVE: OnceLock<regex::Regex> = OnceLock::new();
+    let re = LIVE.get_or_init(|| regex::Regex::new(r"(?i)([\(\[]|- )live\b|\blive (at|from|in|on)\b").unwrap());
+    re.is_match(name)
+}
+
+/// Version tokens of title (`Song (Extended Mix)` => `["extended", "mix"]`), sorted.
+/// "Original Mix" is the same as no version.
+fn version_tokens(title: &str) -> Vec<String> {
+    static SUFFIX: OnceLock<regex::Regex> = OnceLock::new();
+    static VERSION: OnceLock<regex::Regex> = OnceLock::new();
+    let re = SUFFIX.get_or_init(|| regex::Regex::new(r"(?i)(?:[\(\[]([^\)\]]*)[\)\]]| - (.*)$)").unwrap());
+    let version = VERSION.get_or_init(|| regex::Regex::new(r"(?i)\b(mix|edit|remix|version|dub|rework|bootleg|instrumental|vip|acapella|remaster(ed)?)\b").unwrap());
+    let mut tokens: Vec<String> = re.captures_iter(title)
+        .filter_map(|c| c.get(1).or(c.get(2)))
+        .filter(|m| version.is_match(m.as_str()))
+        .flat_map(|m| m.as_str().split(|c: char| !c.is_alphanumeric()).map(|t| t.to_lowercase()).collect::<Vec<_>>())
+        .filter(|t| !t.is_empty())
+        .collect();
+    tokens.sort();
+    tokens.dedup();
+    if tokens == ["mix", "original"] {
+        return vec![];
+    }
+    tokens
+}
+
+/// Split featured artists from artist or title (`Song (feat. A & B)` => (`Song`, [`A`, `B`]))
+fn split_featured(input: &str) -> (String, Vec<String>) {
+    static FEATURED: OnceLock<regex::Regex> = OnceLock::new();
+    let re = FEATURED.get_or_init(|| regex::Regex::new(r"(?i)\s*[\(\[]?\b(?:feat\.?|ft\.|featuring)\s+([^\)\]]+)[\)\]]?").unwrap());
+    let featured = re.captures_iter(input)
+        .flat_map(|c| c[1].split(|c| c == ',' || c == '&').map(|a| a.trim().to_string()).collect::<Vec<_>>())
+        .filter(|a| !a.is_empty())
+        .collect();
+    (re.replace_all(input, "").trim().to_string(), featured)
+}
+
+/// Normalize textual variants: `&` / `and`, `Pt.` / `Part`, curly quotes, full-width characters
+fn normalize_text(input: &str) -> String {
+    let text: String = input.chars().map(|c| match c {
+        '\u{2018}' | '\u{2019}' | '\u{02BC}' | '`' => '\'',
+        '\u{201C}' | '\u{201D}' => '"',
+        '\u{2010}'..='\u{2015}' => '-',
+        '\u{3000}' => ' ',
+        '\u{FF01}'..='\u{FF5E}' => char::from_u32(c as u32 - 0xFEE0).unwrap_or(c),
+        c => c
+    }).collect();
+    static PART: OnceLock<regex::Regex> = OnceLock::new();
+    let re = PART.get_or_init(|| regex::Regex::new(r"(?i)\bpt\.?(\s|$)").unwrap());
+    let text = re.replace_all(&text, "Part$1");
+    text.replace(" & ", " and ").replace('&', " and ")
+}
+
+/// Lowercase lyrics with only words separated by single spaces, for comparison
+fn normalize_lyrics(lyrics: &str) -> String {
+    lyrics.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(|w| w.to_lowercase())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Regex of filename template (`%artist% - %title%`), other `%placeholders%` match anything.
+/// Fails on repeated placeholders
+pub(crate) fn filename_regex(template: &str) -> Result<regex::Regex, regex::Error> {
+    static PLACEHOLDER: OnceLock<regex::Regex> = OnceLock::new();
+    let placeholder = PLACEHOLDER.get_or_init(|| regex::Regex::new(r"%([a-z_]+)%").unwrap());
+    let mut pattern = String::from("^");
+    let mut last = 0;
+    for m in placeholder.captures_iter(template) {
+        let whole = m.get(0).unwrap();
+        pattern.push_str(&regex::escape(&template[last..whole.start()]));
+        pattern.push_str(&match &m[1] {
+            "artist" => "(?P<artist>.+?)".to_string(),
+            "title" => "(?P<title>.+?)".to_string(),
+            _ => ".+?".to_string(),
+        });
+        last = whole.end();
+    }
+    pattern.push_str(&regex::escape(&template[last..]));
+    pattern.push('$');
+    regex::Regex::new(&pattern)
+}
+
+/// Parse artist and title from filename using regex of the template
+fn parse_filename(template: &regex::Regex, filename: &str) -> (Option<String>, Option<String>) {
+    let captures = match template.captures(filename) {
+        Some(c) => c,
+        None => return (None, None),
+    };
+    let get = |name| captures.name(name).map(|m| m.as_str().trim().to_string()).filter(|v| !v.is_empty());
+    (get("artist"), get("title"))
+}
+
+/// Difference hash of image, `None` if it can't be decoded
+fn dhash(data: &[u8]) -> Option<u64> {
+    let image = image::load_from_memory(data).ok()?.resize_exact(9, 8, FilterType::Triangle).to_luma8();
+    let mut hash = 0u64;
+    for y in 0..8 {
+        for x in 0..8 {
+            hash = (hash << 1) | (image.get_pixel(x, y).0[0] > image.get_pixel(x + 1, y).0[0]) as u64;
+        }
+    }
+    Some(hash)
+}
+
+/// Split combined artist tag (`A; B`, `A x B`, `A vs. B`, `A feat. B`).
+/// `/` and `,` are left to [`split_ambiguous`], they are part of some names (`AC/DC`, `Tyler, The Creator`).
+fn split_artists(input: &str) -> Vec<String> {
+    static SEPARATOR: OnceLock<regex::Regex> = OnceLock::new();
+    let (main, featured) = split_featured(input);
+    let re = SEPARATOR.get_or_init(|| regex::Regex::new(r"(?i)\s*;\s*|\s+(?:x|vs\.?)\s+").unwrap());
+    re.split(&main)
+        .map(|a| a.trim().to_string())
+        .chain(featured)
+        .filter(|a| !a.is_empty())
+        .collect()
+}
+
+/// Split artist on `/` and `,` (`A / B`, `A, B`), `None` if there is nothing to split
+fn split_ambiguous(input: &str) -> Option<Vec<String>> {
+    let artists: Vec<String> = input.split(|c| c == '/' || c == ',')
+        .map(|a| a.trim().to_string())
+        .filter(|a| !a.is_empty())
+        .collect();
+    match artists.len() {
+        0 | 1 => None,
+        _ => Some(artists)
+    }
+}
+
+/// Album name from folder name, without `Artist - ` prefix, year and format (`Artist - Album (2001) [FLAC]` => `Album`)
+fn clean_folder_name(folder: &str) -> String {
+    static BRACKETS: OnceLock<regex::Regex> = OnceLock::new();
+    let re = BRACKETS.get_or_init(|| regex::Regex::new(r"\s*[\(\[\{][^\)\]\}]*[\)\]\}]").unwrap());
+    let name = re.replace_all(folder, "");
+    name.split_once(" - ").map(|(_, album)| album).unwrap_or(&name).trim().to_string()
+}
+
+/// Get first value of a tag from file, for any of the format specific keys
+fn file_tag(info: &AudioFileInfo, keys: &[&str]) -> Option<String> {
+    keys.iter()
+        .filter_map(|k| info.tags.get(*k).map(|v| v.first()).flatten())
+        .map(|v| v.trim().to_string())
+        .find(|v| !v.is_empty())
+}
+
+/// Catalog song ID, library ones (`i.`) can't be looked up in batches
+fn catalog_id(id: &str) -> bool {
+    !id.is_empty() && id.chars().all(|c| c.is_ascii_digit())
+}
+
+/// Similarity of 2 titles, 0.0 - 1.0
+fn similarity(a: &str, b: &str) -> f64 {
+    let clean = |s: &str| MatchingUtils::clean_title_matching(&normalize_text(s)).to_lowercase();
+    strsim::normalized_levenshtein(&clean(a), &clean(b))
+}
+
+/// Convert audio locale (`en-US`) to language name, `None` for no linguistic content
+pub(crate) fn language_name(locale: &str) -> Option<String> {
+    let code = locale.split(|c| c == '-' || c == '_').next()?.to_lowercase();
+    let name = match code.as_str() {
+        "" | "zxx" => return None,
+        "ar" => "Arabic", "cs" => "Czech", "da" => "Danish", "de" => "German", "el" => "Greek",
+        "en" => "English", "es" => "Spanish", "fi" => "Finnish", "fr" => "French", "he" => "Hebrew",
+        "hi" => "Hindi", "hu" => "Hungarian", "id" => "Indonesian", "it" => "Italian", "ja" => "Japanese",
+        "ko" => "Korean", "nl" => "Dutch", "no" | "nb" => "Norwegian", "pl" => "Polish", "pt" => "Portuguese",
+        "ro" => "Romanian", "ru" => "Russian", "sk" => "Slovak", "sv" => "Swedish", "th" => "Thai",
+        "tr" => "Turkish", "uk" => "Ukrainian", "vi" => "Vietnamese", "zh" | "yue" => "Chinese",
+        _ => return Some(locale.to_string())
+    };
+    Some(name.to_string())
+}
+
+/// Does the audio locale (`en-US`) match wanted locale / language (`en`, `en-GB`, `English`)
+fn locale_matches(wanted: &str, locale: &str) -> bool {
+    let code = |l: &str| l.split(|c| c == '-' || c == '_').next().unwrap_or_default().to_lowercase();
+    if code(locale).is_empty() {
+        return false;
+    }
+    code(wanted) == code(locale) || language_name(locale).map(|n| n.eq_ignore_ascii_case(wanted.trim())).unwrap_or(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_featured_artists() {
+        assert_eq!(split_featured("Song (feat. A & B)"), ("Song".to_string(), vec!["A".to_string(), "B".to_string()]));
+        assert_eq!(split_featured("Artist ft. Other"), ("Artist".to_string(), vec!["Other".to_string()]));
+        assert_eq!(split_featured("Song"), ("Song".to_string(), vec![]));
+    }
+
+    #[test]
+    fn parse_filename_template() {
+        let template = filename_regex("%artist% - %title%").unwrap();
+        assert_eq!(parse_filename(&template, "Artist - Song"), (Some("Artist".to_string()), Some("Song".to_string())));
+        assert_eq!(parse_filename(&template, "Song"), (None, None));
+        let template = filename_regex("%track% %artist% - %title%").unwrap();
+        assert_eq!(parse_filename(&template, "01 Artist - Song"), (Some("Artist".to_string()), Some("Song".to_string())));
+        assert!(filename_regex("%artist% - %artist%").is_err());
+    }
+
+    #[test]
+    fn clean_folder_album() {
+        assert_eq!(clean_folder_name("Artist - Album (2001) [FLAC]"), "Album");
+        assert_eq!(clean_folder_name("Album"), "Album");
+    }
+
+    #[test]
+    fn normalize_textual_variants() {
+        assert_eq!(normalize_text("Rock & Roll Pt. 2"), "Rock and Roll Part 2");
+        assert_eq!(normalize_text("Don\u{2019}t"), "Don't");
+    }
+
+    #[test]
+    fn version_tokens_of_title() {
+        assert_eq!(version_tokens("Song (Extended Mix)"), vec!["extended", "mix"]);
+        assert_eq!(version_tokens("Song - Radio Edit"), vec!["edit", "radio"]);
+        assert!(version_tokens("Song (Original Mix)").is_empty());
+        assert!(version_tokens("Song").is_empty());
+    }
+
+    #[test]
+    fn release_order_of_ties() {
+        use std::cmp::Ordering;
+        assert_eq!(release_order(&MultipleMatchesSort::Default, Some("2001"), Some("2013-05-17")), Ordering::Equal);
+        assert_eq!(release_order(&MultipleMatchesSort::Oldest, Some("2001"), Some("2013-05-17")), Ordering::Less);
+        assert_eq!(release_order(&MultipleMatchesSort::Newest, Some("2001"), Some("2013-05-17")), Ordering::Greater);
+        assert_eq!(release_order(&MultipleMatchesSort::Newest, None, Some("2001")), Ordering::Greater);
+        assert_eq!(release_order(&MultipleMatchesSort::Default, None, Some("2001")), Ordering::Equal);
+    }
+
+    #[test]
+    fn language_names() {
+        assert_eq!(language_name("en-US").as_deref(), Some("English"));
+        assert_eq!(language_name("pt_BR").as_deref(), Some("Portuguese"));
+        assert_eq!(language_name("JA").as_deref(), Some("Japanese"));
+        assert_eq!(language_name("yue-Hant").as_deref(), Some("Chinese"));
+        // Unknown code is kept, no linguistic content has no language
+        assert_eq!(language_name("gd-GB").as_deref(), Some("gd-GB"));
+        assert_eq!(language_name("zxx"), None);
+        assert_eq!(language_name(""), None);
+    }
+
+    #[test]
+    fn live_titles() {
+        assert!(is_live("Song (Live)"));
+        assert!(is_live("Song - Live at Wembley"));
+        assert!(!is_live("Alive"));
+    }
+
+    #[test]
+    fn split_artists_explicit_separators() {
+        assert_eq!(split_artists("A; B"), vec!["A", "B"]);
+        assert_eq!(split_artists("A x B"), vec!["A", "B"]);
+        assert_eq!(split_artists("A vs. B"), vec!["A", "B"]);
+        assert_eq!(split_artists("A feat. B & C"), vec!["A", "B", "C"]);
+    }
+
+    #[test]
+    fn split_artists_keeps_names() {
+        assert_eq!(split_artists("AC/DC"), vec!["AC/DC"]);
+        assert_eq!(split_artists("Tyler, The Creator"), vec!["Tyler, The Creator"]);
+        assert_eq!(split_artists("Earth, Wind & Fire"), vec!["Earth, Wind & Fire"]);
+    }
+
+    #[test]
+    fn split_ambiguous_separators() {
+        assert_eq!(split_ambiguous("A / B"), Some(vec!["A".to_string(), "B".to_string()]));
+        assert_eq!(split_ambiguous("AC/DC"), Some(vec!["AC".to_string(), "DC".to_string()]));
+        assert_eq!(split_ambiguous("A"), None);
+    }
+}
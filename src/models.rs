@@ -0,0 +1,726 @@
+//! Apple Music API resources
+
+use std::collections::HashMap;
+use std::sync::OnceLock;
+use serde::{Serialize, Deserialize, Deserializer};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[non_exhaustive]
+pub struct SearchResultsResponse {
+    pub results: SearchResults
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[non_exhaustive]
+pub struct SearchResults {
+    // pub album: SearchResult<AlbumAttributes>,
+    // pub artist: SearchResult<ArtistAttributes>,
+    pub song: SearchResult<SongAttributes>
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[non_exhaustive]
+pub struct LibrarySearchResponse {
+    pub results: LibrarySearchResults
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[non_exhaustive]
+pub struct LibrarySearchResults {
+    /// Missing if there are no results
+    #[serde(rename = "library-songs")]
+    pub library_songs: Option<SearchResult<SongAttributes>>
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[non_exhaustive]
+pub struct ArtistSearchResponse {
+    pub results: ArtistSearchResults
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[non_exhaustive]
+pub struct ArtistSearchResults {
+    /// Missing if there are no results
+    pub artist: Option<SearchResult<ArtistAttributes>>
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[non_exhaustive]
+#[serde(bound(deserialize = "I: DeserializeOwned"))]
+pub struct SearchResult<I> {
+    #[serde(default, deserialize_with = "skip_malformed")]
+    pub data: Vec<ItemMeta<I>>,
+    /// Missing in library search
+    #[serde(default)]
+    pub group_id: String,
+    #[serde(default)]
+    pub name: String
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[non_exhaustive]
+#[serde(bound(deserialize = "A: DeserializeOwned"))]
+pub struct DataResponse<A> {
+    #[serde(default, deserialize_with = "skip_malformed")]
+    pub data: Vec<ItemMeta<A>>,
+    /// Path of next page of paginated responses
+    pub next: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[non_exhaustive]
+pub struct ItemMeta<A> {
+    pub attributes: A,
+    pub href: String,
+    pub id: String,
+    /// Lyric snippets and such
+    pub meta: Option<Value>,
+    pub relationships: Option<Relationships>,
+}
+
+impl ItemMeta<SongAttributes> {
+    /// Album from relationships
+    pub fn album(&self) -> Option<&ItemMeta<AlbumAttributes>> {
+        self.relationships.as_ref()?.albums.as_ref()?.data.first()
+    }
+
+    /// Is Apple Digital Master (Mastered for iTunes)
+    pub fn digital_master(&self) -> bool {
+        self.attributes.is_apple_digital_master.unwrap_or(false)
+            || self.album().map(|a| a.attributes.is_mastered_for_itunes.unwrap_or(false)).unwrap_or(false)
+    }
+
+    /// Artists from relationship, falls back to combined `artistName`
+    pub fn artists(&self) -> Vec<String> {
+        match self.relationships.as_ref().map(|r| r.artists.as_ref()).flatten() {
+            Some(artists) if !artists.data.is_empty() => artists.data.iter().map(|a| a.attributes.name.to_string()).collect(),
+            _ => vec![self.attributes.artist_name.to_string()]
+        }
+    }
+
+    /// Lyric snippets from search meta (`with=lyricHighlights`)
+    pub fn lyric_highlights(&self) -> Vec<String> {
+        let highlights = match self.meta.as_ref().map(|m| m.get("lyricHighlights")).flatten() {
+            Some(Value::Array(highlights)) => highlights,
+            _ => return vec![],
+        };
+        highlights.iter().filter_map(|h| match h {
+            Value::String(text) => Some(text.to_string()),
+            _ => h.get("text").map(|t| t.as_str()).flatten().map(String::from),
+        }).collect()
+    }
+
+    /// Artist IDs from relationship
+    pub fn artist_ids(&self) -> Vec<String> {
+        self.relationships.as_ref().map(|r| r.artists.as_ref()).flatten()
+            .map(|a| a.data.iter().map(|a| a.id.to_string()).collect())
+            .unwrap_or_default()
+    }
+
+    /// Album ID from relationship or URL
+    pub fn album_id(&self) -> Option<String> {
+        self.album().map(|a| a.id.to_string()).or_else(|| album_id_from_url(&self.attributes.url))
+    }
+
+    /// Release rank for preferring original albums, lower is better:
+    /// album, EP, single, greatest hits, compilation
+    pub fn release_rank(&self) -> u8 {
+        let album = match self.album() {
+            Some(album) => album,
+            None => return 0,
+        };
+        if album.compilation() {
+            return 4;
+        }
+        static GREATEST_HITS: OnceLock<regex::Regex> = OnceLock::new();
+        let greatest_hits = GREATEST_HITS.get_or_init(|| regex::Regex::new(r"(?i)\b(greatest hits|best of|the very best|the essential|anthology|collection|hits)\b").unwrap());
+        if greatest_hits.is_match(&album.attributes.name) {
+            return 3;
+        }
+        match album.release_type() {
+            "Single" => 2,
+            "EP" => 1,
+            _ => 0
+        }
+    }
+}
+
+impl ItemMeta<AlbumAttributes> {
+    /// Album artists from relationship, falls back to combined `artistName`
+    pub fn artists(&self) -> Vec<String> {
+        match self.relationships.as_ref().map(|r| r.artists.as_ref()).flatten() {
+            Some(artists) if !artists.data.is_empty() => artists.data.iter().map(|a| a.attributes.name.to_string()).collect(),
+            _ => vec![self.attributes.artist_name.to_string()]
+        }
+    }
+
+    /// Canonical label from relationship, falls back to free text `recordLabel`
+    pub fn label(&self) -> Option<String> {
+        self.relationships.as_ref().map(|r| r.record_labels.as_ref()).flatten()
+            .map(|l| l.data.first().map(|l| l.attributes.name.to_string()))
+            .flatten()
+            .or(self.attributes.record_label.clone())
+    }
+
+    /// Number of discs from tracklist
+    pub fn disc_total(&self) -> Option<u16> {
+        let tracks = self.relationships.as_ref()?.tracks.as_ref()?;
+        tracks.data.iter().filter_map(|t| t.attributes.disc_number).max().map(|d| d as u16)
+    }
+
+    /// `Single`, `EP` or `Album` from attributes, name suffix and track count
+    pub fn release_type(&self) -> &'static str {
+        let name = &self.attributes.name;
+        if self.attributes.is_single.unwrap_or(false) || name.ends_with(" - Single") {
+            return "Single";
+        }
+        if name.ends_with(" - EP") || self.attributes.track_count.map(|c| (2..=6).contains(&c)).unwrap_or(false) {
+            return "EP";
+        }
+        "Album"
+    }
+
+    /// Is compilation, by attribute or "Various Artists" album artist
+    pub fn compilation(&self) -> bool {
+        self.attributes.is_compilation.unwrap_or(false)
+            || self.artists().iter().any(|a| a.eq_ignore_ascii_case("various artists"))
+    }
+}
+
+/// Get album ID from song URL (`https://music.apple.com/gb/album/name/123?i=456`)
+pub(crate) fn album_id_from_url(url: &str) -> Option<String> {
+    if !url.contains("/album/") {
+        return None;
+    }
+    let id = url.split('?').next()?.trim_end_matches('/').rsplit('/').next()?;
+    match !id.is_empty() && id.chars().all(|c| c.is_ascii_digit()) {
+        true => Some(id.to_string()),
+        false => None
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[non_exhaustive]
+pub struct SongAttributes {
+    /// Can be missing for library uploads
+    #[serde(default)]
+    pub album_name: String,
+    pub artist_name: String,
+    pub artist_url: Option<String>,
+    /// Missing on some unreleased or region locked songs
+    pub artwork: Option<AppleMusicArtwork>,
+    pub audio_locale: Option<String>,
+    /// `atmos`, `lossless`, `hi-res-lossless`, `spatial`, `lossy-stereo`
+    #[serde(default)]
+    pub audio_traits: Vec<String>,
+    /// Only with `extend=audioVariants`, `dolby-atmos`, `lossless`, ...
+    pub audio_variants: Option<Vec<String>>,
+    pub composer_name: Option<String>,
+    /// `explicit` or `clean`, missing if not rated
+    pub content_rating: Option<String>,
+    /// 0 if missing (library uploads)
+    #[serde(default)]
+    pub disc_number: i32,
+    pub duration_in_millis: u64,
+    #[serde(default)]
+    pub genre_names: Vec<String>,
+    #[serde(default)]
+    pub has_lyrics: bool,
+    #[serde(default)]
+    pub has_time_synced_lyrics: bool,
+    pub is_apple_digital_master: Option<bool>,
+    pub isrc: Option<String>,
+    pub name: String,
+    /// Missing for songs which are not playable
+    pub play_params: Option<Value>,
+    #[serde(default)]
+    pub previews: Vec<AppleMusicPreview>,
+    /// Editorial moods, only with `extend=moods` on annotated content
+    #[serde(default)]
+    pub moods: Vec<String>,
+    /// Editorial activities, only with `extend=activities` on annotated content
+    #[serde(default)]
+    pub activities: Vec<String>,
+    /// Can be year or NativeDate
+    pub release_date: Option<String>,
+    /// 0 if missing (library uploads)
+    #[serde(default)]
+    pub track_number: i32,
+    /// Missing for library uploads
+    #[serde(default)]
+    pub url: String,
+    /// Classical only, composer attribution
+    pub attribution: Option<String>,
+    /// Classical only
+    pub work_name: Option<String>,
+    /// Classical only
+    pub movement_name: Option<String>,
+    /// Classical only
+    pub movement_number: Option<u16>,
+    /// Classical only
+    pub movement_count: Option<u16>,
+    /// Attributes which are not mapped
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+impl SongAttributes {
+    /// Whether the song is explicit, `None` if not rated
+    pub fn explicit(&self) -> Option<bool> {
+        match self.content_rating.as_deref() {
+            Some("explicit") => Some(true),
+            Some("clean") => Some(false),
+            _ => None
+        }
+    }
+
+    /// Normalized audio variants (`atmos`, `lossless`, `hi-res-lossless`, `spatial`), lossy stereo is omitted
+    pub fn audio_variants(&self) -> Vec<String> {
+        let mut variants: Vec<String> = vec![];
+        for variant in self.audio_traits.iter().chain(self.audio_variants.iter().flatten()) {
+            let variant = match variant.as_str() {
+                "dolby-atmos" => "atmos",
+                "lossy-stereo" | "dolby-audio" => continue,
+                v => v
+            };
+            if !variants.iter().any(|v| v == variant) {
+                variants.push(variant.to_string());
+            }
+        }
+        variants
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[non_exhaustive]
+pub struct AppleMusicArtwork {
+    pub url: String,
+    /// 0 if unknown (library uploads)
+    #[serde(default)]
+    pub width: u64,
+    #[serde(default)]
+    pub height: u64,
+    /// Hex color without `#`
+    pub bg_color: Option<String>,
+    pub text_color1: Option<String>,
+    pub text_color2: Option<String>,
+    pub text_color3: Option<String>,
+    pub text_color4: Option<String>,
+}
+
+impl AppleMusicArtwork {
+    /// Render URL template, size is clamped to the source dimensions if known
+    pub fn url_for(&self, width: u64, height: u64, format: ArtworkFormat, crop: ArtworkCrop) -> String {
+        let (width, height) = match self.known_size() {
+            true => (width.min(self.width), height.min(self.height)),
+            false => (width, height)
+        };
+        self.url
+            .replace("{w}", &width.to_string())
+            .replace("{h}", &height.to_string())
+            .replace("{f}", format.extension())
+            .replace("{c}", crop.code())
+    }
+
+    /// Whether source dimensions are known, library uploads have 0 x 0
+    pub fn known_size(&self) -> bool {
+        self.width > 0 && self.height > 0
+    }
+
+    /// Size fitting into `max` x `max` keeping aspect ratio, never upscaled. `max` x `max` if size is unknown
+    pub fn fit(&self, max: u64) -> (u64, u64) {
+        if !self.known_size() {
+            return (max, max);
+        }
+        let scale = (max as f64 / self.width.max(self.height).max(1) as f64).min(1.0);
+        ((self.width as f64 * scale).round() as u64, (self.height as f64 * scale).round() as u64)
+    }
+}
+
+/// Motion artwork, only with `extend=editorialVideo`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[non_exhaustive]
+pub struct EditorialVideo {
+    pub motion_detail_square: Option<EditorialVideoAsset>,
+    #[serde(rename = "motionSquareVideo1x1")]
+    pub motion_square_video_1x1: Option<EditorialVideoAsset>,
+    pub motion_detail_tall: Option<EditorialVideoAsset>,
+}
+
+impl EditorialVideo {
+    /// Square looping video (HLS) URL
+    pub fn square(&self) -> Option<&str> {
+        self.motion_detail_square.as_ref().or(self.motion_square_video_1x1.as_ref()).map(|v| v.video.as_str())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[non_exhaustive]
+pub struct EditorialVideoAsset {
+    /// HLS playlist
+    pub video: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[non_exhaustive]
+pub struct AppleMusicPreview {
+    pub url: String
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[non_exhaustive]
+pub struct Relationships {
+    pub albums: Option<RelationshipWrap<AlbumAttributes>>,
+    pub artists: Option<RelationshipWrap<ArtistAttributes>>,
+    /// Composer artist entities
+    pub composers: Option<RelationshipWrap<ArtistAttributes>>,
+    /// Album tracklist
+    pub tracks: Option<RelationshipWrap<AlbumTrackAttributes>>,
+    #[serde(rename = "record-labels")]
+    pub record_labels: Option<RelationshipWrap<RecordLabelAttributes>>,
+    /// Genre entities of song
+    pub genres: Option<RelationshipWrap<GenreAttributes>>,
+    /// Catalog version of library song, only with `include=catalog`
+    pub catalog: Option<RelationshipWrap<SongAttributes>>,
+    /// Albums / playlists / stations of recommendation
+    pub contents: Option<RelationshipWrap<ResourceAttributes>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[non_exhaustive]
+#[serde(bound(deserialize = "D: DeserializeOwned"))]
+pub struct RelationshipWrap<D> {
+    pub href: String,
+    #[serde(default, deserialize_with = "skip_malformed")]
+    pub data: Vec<ItemMeta<D>>
+}
+
+/// Minimal attributes of album tracks, can be songs or music videos
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[non_exhaustive]
+pub struct AlbumTrackAttributes {
+    pub name: String,
+    pub artist_name: Option<String>,
+    pub disc_number: Option<i32>,
+    pub track_number: Option<i32>,
+    pub duration_in_millis: Option<u64>,
+    pub isrc: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[non_exhaustive]
+pub struct LibraryPlaylistAttributes {
+    pub name: String,
+    pub can_edit: Option<bool>,
+    pub date_added: Option<String>,
+}
+
+/// Common attributes of albums, playlists and stations in mixed lists
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[non_exhaustive]
+pub struct ResourceAttributes {
+    pub name: String,
+    pub artist_name: Option<String>,
+    pub artwork: Option<AppleMusicArtwork>,
+    pub url: Option<String>,
+    /// Type specific attributes
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[non_exhaustive]
+pub struct RecommendationAttributes {
+    pub title: Option<RecommendationTitle>,
+    /// `music-recommendations`, ...
+    pub kind: Option<String>,
+    #[serde(default)]
+    pub is_group_recommendation: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[non_exhaustive]
+pub struct RecommendationTitle {
+    pub string_for_display: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[non_exhaustive]
+pub struct RatingAttributes {
+    /// 1 = loved, -1 = disliked
+    pub value: i8,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[non_exhaustive]
+pub struct RecordLabelAttributes {
+    pub name: String,
+    pub url: Option<String>
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[non_exhaustive]
+pub struct GenreAttributes {
+    pub name: String,
+    pub parent_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[non_exhaustive]
+pub struct ArtistAttributes {
+    pub url: String,
+    pub name: String,
+    /// Only with `extend=artistBio`
+    pub artist_bio: Option<String>,
+    /// Only with `extend=bornOrFormed`
+    pub born_or_formed: Option<String>,
+    /// Only with `extend=origin`
+    pub origin: Option<String>,
+    pub artwork: Option<AppleMusicArtwork>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[non_exhaustive]
+pub struct AlbumAttributes {
+    pub url: Option<String>,
+    /// Can be year or NativeDate
+    pub release_date: Option<String>,
+    pub name: String,
+    pub artist_name: String,
+    pub artist_url: Option<String>,
+    pub artwork: Option<AppleMusicArtwork>,
+    pub record_label: Option<String>,
+    pub track_count: Option<u16>,
+    pub upc: Option<String>,
+    pub is_mastered_for_itunes: Option<bool>,
+    pub is_compilation: Option<bool>,
+    pub is_single: Option<bool>,
+    /// Only in full album resource
+    pub copyright: Option<String>,
+    /// Only in full album resource
+    pub editorial_notes: Option<EditorialNotes>,
+    pub editorial_video: Option<EditorialVideo>,
+    /// Additional images (banners, flowcase, back covers) by kind, only with `extend=editorialArtwork`
+    pub editorial_artwork: Option<HashMap<String, AppleMusicArtwork>>,
+    /// Attributes which are not mapped
+    #[serde(flatten)]
+    pub extra: HashMap<String, Value>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[non_exhaustive]
+pub struct EditorialNotes {
+    pub short: Option<String>,
+    pub standard: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[non_exhaustive]
+pub struct CreditsResponse {
+    pub data: Vec<CreditCategory>
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[non_exhaustive]
+pub struct CreditCategory {
+    pub id: String,
+    pub attributes: CreditCategoryAttributes,
+    pub relationships: Option<CreditCategoryRelationships>
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[non_exhaustive]
+pub struct CreditCategoryAttributes {
+    pub title: String,
+    /// `performer`, `composition_lyrics`, `production_engineering`
+    pub kind: Option<String>
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[non_exhaustive]
+pub struct CreditCategoryRelationships {
+    #[serde(rename = "credit-artists")]
+    pub credit_artists: Option<CreditArtists>
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[non_exhaustive]
+pub struct CreditArtists {
+    pub data: Vec<CreditArtist>
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[non_exhaustive]
+pub struct CreditArtist {
+    pub id: String,
+    pub attributes: CreditArtistAttributes
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[non_exhaustive]
+pub struct CreditArtistAttributes {
+    pub name: String,
+    #[serde(default)]
+    pub role_names: Vec<String>
+}
+
+/// Deserialize list skipping items which fail to parse instead of failing the whole response
+fn skip_malformed<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error> where D: Deserializer<'de>, T: DeserializeOwned {
+    let items = Vec::<Value>::deserialize(deserializer)?;
+    Ok(items.into_iter().filter_map(|item| {
+        let id = item.get("id").map(|id| id.to_string()).unwrap_or_default();
+        match serde_json::from_value(item) {
+            Ok(item) => Some(item),
+            Err(e) => {
+                warn!("Skipping malformed Apple Music item {id}: {e}");
+                None
+            }
+        }
+    }).collect())
+}
+
+/// Format of artwork requested from Apple
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ArtworkFormat {
+    Jpg,
+    Png,
+    Webp,
+}
+
+impl ArtworkFormat {
+    /// Value of `{f}` in artwork URL
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ArtworkFormat::Jpg => "jpg",
+            ArtworkFormat::Png => "png",
+            ArtworkFormat::Webp => "webp",
+        }
+    }
+}
+
+/// Artwork crop variant (`{c}` in artwork URL)
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ArtworkCrop {
+    /// Square crop
+    Square,
+    /// Bounding box, full artwork as delivered
+    Bb,
+    /// Center crop
+    Cc,
+    /// Source aspect ratio
+    Sr,
+}
+
+impl ArtworkCrop {
+    /// Value of `{c}` in artwork URL
+    pub fn code(&self) -> &'static str {
+        match self {
+            ArtworkCrop::Square => "",
+            ArtworkCrop::Bb => "bb",
+            ArtworkCrop::Cc => "cc",
+            ArtworkCrop::Sr => "sr",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture_album() -> ItemMeta<AlbumAttributes> {
+        let response: DataResponse<AlbumAttributes> = serde_json::from_str(include_str!("../tests/fixtures/album.json")).unwrap();
+        response.data.into_iter().next().unwrap()
+    }
+
+    fn artwork(width: u64, height: u64) -> AppleMusicArtwork {
+        AppleMusicArtwork {
+            url: "https://is1-ssl.mzstatic.com/image/thumb/Music/cover.jpg/{w}x{h}{c}.{f}".to_string(),
+            width, height,
+            bg_color: None, text_color1: None, text_color2: None, text_color3: None, text_color4: None,
+        }
+    }
+
+    #[test]
+    fn release_type_thresholds() {
+        let mut album = fixture_album();
+        assert_eq!(album.release_type(), "Album");
+        album.attributes.track_count = Some(6);
+        assert_eq!(album.release_type(), "EP");
+        album.attributes.track_count = Some(7);
+        assert_eq!(album.release_type(), "Album");
+        album.attributes.track_count = Some(1);
+        assert_eq!(album.release_type(), "Album");
+        album.attributes.name = "Get Lucky - Single".to_string();
+        assert_eq!(album.release_type(), "Single");
+        album.attributes.name = "Get Lucky - EP".to_string();
+        assert_eq!(album.release_type(), "EP");
+        album.attributes.is_single = Some(true);
+        assert_eq!(album.release_type(), "Single");
+    }
+
+    #[test]
+    fn album_id_of_url() {
+        assert_eq!(album_id_from_url("https://music.apple.com/gb/album/get-lucky/617154241?i=617154366").as_deref(), Some("617154241"));
+        assert_eq!(album_id_from_url("https://music.apple.com/gb/album/random-access-memories/617154241/").as_deref(), Some("617154241"));
+        assert_eq!(album_id_from_url("https://music.apple.com/gb/album/random-access-memories").as_deref(), None);
+        assert_eq!(album_id_from_url("https://music.apple.com/gb/song/get-lucky/617154366").as_deref(), None);
+    }
+
+    #[test]
+    fn artwork_url_template() {
+        let base = "https://is1-ssl.mzstatic.com/image/thumb/Music/cover.jpg";
+        assert_eq!(artwork(3000, 3000).url_for(1400, 1400, ArtworkFormat::Png, ArtworkCrop::Bb), format!("{base}/1400x1400bb.png"));
+        assert_eq!(artwork(3000, 3000).url_for(600, 600, ArtworkFormat::Jpg, ArtworkCrop::Square), format!("{base}/600x600.jpg"));
+        // Clamped to source size, unknown size is kept
+        assert_eq!(artwork(1000, 800).url_for(1400, 1400, ArtworkFormat::Webp, ArtworkCrop::Cc), format!("{base}/1000x800cc.webp"));
+        assert_eq!(artwork(0, 0).url_for(1400, 1400, ArtworkFormat::Jpg, ArtworkCrop::Sr), format!("{base}/1400x1400sr.jpg"));
+    }
+
+    #[test]
+    fn artwork_fit() {
+        assert_eq!(artwork(3000, 2000).fit(1500), (1500, 1000));
+        assert_eq!(artwork(1000, 3000).fit(1500), (500, 1500));
+        // Never upscaled
+        assert_eq!(artwork(600, 600).fit(1500), (600, 600));
+        assert_eq!(artwork(0, 0).fit(1500), (1500, 1500));
+    }
+}
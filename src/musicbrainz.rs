@@ -0,0 +1,79 @@
+//! MusicBrainz recording / release IDs by ISRC
+
+use std::error::Error;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use reqwest::StatusCode;
+use serde::Deserialize;
+use crate::AppleMusic;
+
+const MUSICBRAINZ_URL: &'static str = "https://musicbrainz.org/ws/2";
+/// MusicBrainz allows 1 request per second
+const REQUEST_INTERVAL: Duration = Duration::from_millis(1100);
+
+/// Time of the last request, shared by all threads
+static LAST_REQUEST: Mutex<Option<Instant>> = Mutex::new(None);
+
+#[derive(Debug, Clone, Deserialize)]
+struct IsrcResponse {
+    #[serde(default)]
+    recordings: Vec<Recording>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct Recording {
+    id: String,
+    #[serde(default)]
+    releases: Vec<Release>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct Release {
+    id: String,
+    title: String,
+    #[serde(rename = "release-group")]
+    release_group: Option<ReleaseGroup>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct ReleaseGroup {
+    id: String,
+}
+
+/// MBIDs of recording and the release it was matched on
+#[derive(Debug, Clone)]
+pub(crate) struct MusicBrainzIds {
+    pub recording: String,
+    pub release: Option<String>,
+    pub release_group: Option<String>,
+}
+
+impl AppleMusic {
+    /// Look up recording by ISRC, release with `album` title is preferred. `None` if ISRC is unknown
+    pub(crate) fn musicbrainz_ids(&self, isrc: &str, album: &str) -> Result<Option<MusicBrainzIds>, Box<dyn Error>> {
+        {
+            let mut last = LAST_REQUEST.lock().unwrap();
+            if let Some(wait) = last.map(|l| REQUEST_INTERVAL.checked_sub(l.elapsed())).flatten() {
+                std::thread::sleep(wait);
+            }
+            *last = Some(Instant::now());
+        }
+        let response = self.third_party.get(format!("{MUSICBRAINZ_URL}/isrc/{isrc}"))
+            .query(&[("inc", "releases+release-groups"), ("fmt", "json")])
+            .send()?;
+        if response.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let r: IsrcResponse = response.error_for_status()?.json()?;
+        let recording = match r.recordings.into_iter().next() {
+            Some(recording) => recording,
+            None => return Ok(None)
+        };
+        let release = recording.releases.iter().find(|r| r.title.eq_ignore_ascii_case(album)).or(recording.releases.first());
+        Ok(Some(MusicBrainzIds {
+            release: release.map(|r| r.id.to_string()),
+            release_group: release.map(|r| r.release_group.as_ref().map(|g| g.id.to_string())).flatten(),
+            recording: recording.id,
+        }))
+    }
+}
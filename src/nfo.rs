@@ -0,0 +1,114 @@
+//! Kodi / Jellyfin `album.nfo` rendering
+
+use crate::models::*;
+
+/// Render album NFO, `review` is plain text editorial notes
+pub(crate) fn album_nfo(album: &ItemMeta<AlbumAttributes>, thumb: Option<&str>, review: Option<&str>) -> String {
+    let attributes = &album.attributes;
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n<album>\n");
+    let mut element = |name: &str, value: &str| out.push_str(&format!("    <{name}>{}</{name}>\n", escape(value)));
+    element("title", &attributes.name);
+    for artist in album.artists() {
+        element("artist", &artist);
+        element("albumartist", &artist);
+    }
+    let genres = attributes.extra.get("genreNames").map(|g| g.as_array()).flatten().into_iter().flatten();
+    for genre in genres.filter_map(|g| g.as_str()).filter(|g| *g != "Music") {
+        element("genre", genre);
+    }
+    if let Some(date) = attributes.release_date.as_deref() {
+        element("year", date.get(0..4).unwrap_or(date));
+        if date.len() == 10 {
+            element("releasedate", date);
+        }
+    }
+    if let Some(label) = album.label() {
+        element("label", &label);
+    }
+    // Not read by Kodi, for Jellyfin / cataloging
+    if let Some(upc) = attributes.upc.as_deref() {
+        element("upc", upc);
+    }
+    element("releasetype", album.release_type());
+    if let Some(copyright) = attributes.copyright.as_deref() {
+        element("copyright", copyright);
+    }
+    if let Some(review) = review {
+        element("review", review);
+    }
+    if let Some(url) = attributes.url.as_deref() {
+        element("url", url);
+    }
+    if let Some(thumb) = thumb {
+        out.push_str(&format!("    <thumb aspect=\"thumb\">{}</thumb>\n", escape(thumb)));
+    }
+    let tracks = album.relationships.as_ref().map(|r| r.tracks.as_ref()).flatten().map(|t| t.data.as_slice()).unwrap_or_default();
+    for track in tracks.iter().filter(|t| t.href.contains("/songs/")) {
+        out.push_str("    <track>\n");
+        if let Some(disc) = track.attributes.disc_number {
+            out.push_str(&format!("        <disc>{disc}</disc>\n"));
+        }
+        if let Some(position) = track.attributes.track_number {
+            out.push_str(&format!("        <position>{position}</position>\n"));
+        }
+        out.push_str(&format!("        <title>{}</title>\n", escape(&track.attributes.name)));
+        if let Some(duration) = track.attributes.duration_in_millis {
+            let seconds = duration / 1000;
+            out.push_str(&format!("        <duration>{}:{:02}</duration>\n", seconds / 60, seconds % 60));
+        }
+        out.push_str("    </track>\n");
+    }
+    out.push_str("</album>\n");
+    out
+}
+
+/// Escape XML text
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture_album() -> ItemMeta<AlbumAttributes> {
+        let response: DataResponse<AlbumAttributes> = serde_json::from_str(include_str!("../tests/fixtures/album.json")).unwrap();
+        response.data.into_iter().next().unwrap()
+    }
+
+    #[test]
+    fn escape_text() {
+        assert_eq!(escape(r#"Rock & Roll <"Live">"#), "Rock &amp; Roll &lt;&quot;Live&quot;&gt;");
+        assert_eq!(escape("&amp;"), "&amp;amp;");
+    }
+
+    #[test]
+    fn album_nfo_fixture() {
+        let mut album = fixture_album();
+        // Music videos of the tracklist are left out
+        album.relationships.as_mut().unwrap().tracks.as_mut().unwrap().data.push(serde_json::from_str(r#"{
+            "id": "1000000000", "type": "music-videos", "href": "/v1/catalog/gb/music-videos/1000000000",
+            "attributes": { "name": "Get Lucky (Video)", "trackNumber": 14 }
+        }"#).unwrap());
+        let nfo = album_nfo(&album, Some("https://example.com/cover.jpg?w=1&h=1"), Some("Disco <3"));
+        assert!(nfo.starts_with("<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n<album>\n    <title>Random Access Memories</title>\n"));
+        assert!(nfo.contains("    <artist>Daft Punk</artist>\n    <albumartist>Daft Punk</albumartist>\n"));
+        assert!(nfo.contains("    <genre>Electronic</genre>\n"));
+        assert!(!nfo.contains("<genre>Music</genre>"));
+        assert!(nfo.contains("    <year>2013</year>\n    <releasedate>2013-05-17</releasedate>\n"));
+        assert!(nfo.contains("    <label>Columbia Records</label>\n"));
+        assert!(nfo.contains("    <review>Disco &lt;3</review>\n"));
+        assert!(nfo.contains("    <thumb aspect=\"thumb\">https://example.com/cover.jpg?w=1&amp;h=1</thumb>\n"));
+        assert!(nfo.contains("        <title>Get Lucky (feat. Pharrell Williams &amp; Nile Rodgers)</title>\n        <duration>6:09</duration>\n"));
+        assert_eq!(nfo.matches("<track>").count(), 2);
+        assert!(!nfo.contains("Video"));
+        assert!(nfo.ends_with("</album>\n"));
+
+        // Year only precision has no release date
+        album.attributes.release_date = Some("2013".to_string());
+        let nfo = album_nfo(&album, None, None);
+        assert!(nfo.contains("    <year>2013</year>\n"));
+        assert!(!nfo.contains("<releasedate>"));
+        assert!(!nfo.contains("<thumb") && !nfo.contains("<review>"));
+    }
+}
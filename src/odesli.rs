@@ -0,0 +1,39 @@
+//! Platform agnostic song.link URLs from Odesli
+
+use std::error::Error;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use serde::Deserialize;
+use crate::AppleMusic;
+
+const ODESLI_URL: &'static str = "https://api.song.link/v1-alpha.1/links";
+/// Odesli allows 10 requests per minute without API key
+const REQUEST_INTERVAL: Duration = Duration::from_secs(6);
+
+/// Time of the last request, shared by all threads
+static LAST_REQUEST: Mutex<Option<Instant>> = Mutex::new(None);
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LinksResponse {
+    page_url: String,
+}
+
+impl AppleMusic {
+    /// Resolve song.link URL of Apple Music URL
+    pub(crate) fn song_link(&self, url: &str) -> Result<String, Box<dyn Error>> {
+        {
+            let mut last = LAST_REQUEST.lock().unwrap();
+            if let Some(wait) = last.map(|l| REQUEST_INTERVAL.checked_sub(l.elapsed())).flatten() {
+                std::thread::sleep(wait);
+            }
+            *last = Some(Instant::now());
+        }
+        let r: LinksResponse = self.third_party.get(ODESLI_URL)
+            .query(&[("url", url)])
+            .send()?
+            .error_for_status()?
+            .json()?;
+        Ok(r.page_url)
+    }
+}
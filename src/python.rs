@@ -0,0 +1,77 @@
+//! Python module, results are converted to plain dicts / lists
+//!
+//! ```python
+//! from onetagger_apple_music import AppleMusic
+//! am = AppleMusic(media_user_token, language="en_GB")
+//! songs = am.search("artist title")["song"]["data"]
+//! ```
+
+use std::error::Error;
+use pyo3::prelude::*;
+use pyo3::exceptions::{PyRuntimeError, PyValueError};
+use serde::Serialize;
+use crate::AppleMusic;
+
+#[pyclass(name = "AppleMusic")]
+struct PyAppleMusic {
+    inner: AppleMusic,
+}
+
+#[pymethods]
+impl PyAppleMusic {
+    #[new]
+    #[pyo3(signature = (media_user_token, language = None))]
+    fn new(media_user_token: &str, language: Option<&str>) -> PyResult<Self> {
+        let mut inner = AppleMusic::new(media_user_token).map_err(|e| PyValueError::new_err(e.to_string()))?;
+        if let Some(language) = language {
+            inner.set_language(language);
+        }
+        Ok(PyAppleMusic { inner })
+    }
+
+    /// Search songs
+    fn search(&self, py: Python<'_>, query: &str) -> PyResult<PyObject> {
+        run(py, || to_json(&self.inner.search(query)?))
+    }
+
+    /// Song with album / artist relationships
+    fn song(&self, py: Python<'_>, song_id: &str) -> PyResult<PyObject> {
+        run(py, || to_json(&self.inner.song(song_id)?))
+    }
+
+    /// Album with tracklist
+    fn album(&self, py: Python<'_>, album_id: &str) -> PyResult<PyObject> {
+        run(py, || to_json(&self.inner.album(album_id)?))
+    }
+
+    /// Karaoke lyrics, or LRC string with `lrc=True`
+    #[pyo3(signature = (song_id, lrc = false))]
+    fn lyrics(&self, py: Python<'_>, song_id: &str, lrc: bool) -> PyResult<PyObject> {
+        let lyrics = py.allow_threads(|| self.inner.karaoke_lyrics(song_id).map_err(|e| e.to_string()))
+            .map_err(PyRuntimeError::new_err)?;
+        match lrc {
+            true => Ok(lyrics.to_lrc().into_py(py)),
+            false => json_loads(py, &serde_json::to_string(&lyrics).map_err(|e| PyRuntimeError::new_err(e.to_string()))?),
+        }
+    }
+}
+
+/// Run request without holding the GIL and convert the JSON output
+fn run(py: Python<'_>, f: impl FnOnce() -> Result<String, Box<dyn Error>> + Send) -> PyResult<PyObject> {
+    let json = py.allow_threads(|| f().map_err(|e| e.to_string())).map_err(PyRuntimeError::new_err)?;
+    json_loads(py, &json)
+}
+
+fn to_json<T: Serialize>(value: &T) -> Result<String, Box<dyn Error>> {
+    Ok(serde_json::to_string(value)?)
+}
+
+fn json_loads(py: Python<'_>, json: &str) -> PyResult<PyObject> {
+    Ok(py.import("json")?.call_method1("loads", (json,))?.into())
+}
+
+#[pymodule]
+fn onetagger_apple_music(_py: Python<'_>, m: &PyModule) -> PyResult<()> {
+    m.add_class::<PyAppleMusic>()?;
+    Ok(())
+}
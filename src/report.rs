@@ -0,0 +1,77 @@
+//! Structured album reports for discography databases
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use serde::Serialize;
+use crate::AppleMusic;
+use crate::models::*;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AlbumReport {
+    pub id: String,
+    pub name: String,
+    pub artists: Vec<String>,
+    pub release_date: Option<String>,
+    pub release_type: &'static str,
+    pub upc: Option<String>,
+    pub label: Option<String>,
+    pub copyright: Option<String>,
+    pub track_count: Option<u16>,
+    pub url: Option<String>,
+    /// Full size, uncropped
+    pub artwork_url: Option<String>,
+    /// Additional images (banners, back covers) by kind
+    pub editorial_artwork: BTreeMap<String, String>,
+    pub tracks: Vec<ReportTrack>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReportTrack {
+    pub id: String,
+    pub disc_number: Option<i32>,
+    pub track_number: Option<i32>,
+    pub name: String,
+    pub artist: Option<String>,
+    pub isrc: Option<String>,
+    pub duration_ms: Option<u64>,
+}
+
+impl AlbumReport {
+    /// Build report from full album resource, music videos are left out
+    pub fn new(album: &ItemMeta<AlbumAttributes>) -> AlbumReport {
+        let full = |artwork: &AppleMusicArtwork| artwork.url_for(artwork.width, artwork.height, ArtworkFormat::Jpg, ArtworkCrop::Bb);
+        let tracks = album.relationships.as_ref().map(|r| r.tracks.as_ref()).flatten().map(|t| t.data.as_slice()).unwrap_or_default();
+        AlbumReport {
+            id: album.id.to_string(),
+            name: album.attributes.name.to_string(),
+            artists: album.artists(),
+            release_date: album.attributes.release_date.clone(),
+            release_type: album.release_type(),
+            upc: album.attributes.upc.clone(),
+            label: album.label(),
+            copyright: album.attributes.copyright.clone(),
+            track_count: album.attributes.track_count,
+            url: album.attributes.url.clone(),
+            artwork_url: album.attributes.artwork.as_ref().map(full),
+            editorial_artwork: album.attributes.editorial_artwork.iter().flatten().map(|(kind, artwork)| (kind.to_string(), full(artwork))).collect(),
+            tracks: tracks.iter().filter(|t| t.href.contains("/songs/")).map(|t| ReportTrack {
+                id: t.id.to_string(),
+                disc_number: t.attributes.disc_number,
+                track_number: t.attributes.track_number,
+                name: t.attributes.name.to_string(),
+                artist: t.attributes.artist_name.clone(),
+                isrc: t.attributes.isrc.clone(),
+                duration_ms: t.attributes.duration_in_millis,
+            }).collect(),
+        }
+    }
+}
+
+impl AppleMusic {
+    /// Fetch album and build its report
+    pub fn album_report(&self, album_id: &str) -> Result<AlbumReport, Box<dyn Error>> {
+        Ok(AlbumReport::new(&*self.shared_album(album_id)?))
+    }
+}
@@ -0,0 +1,1987 @@
+//! OneTagger plugin: config, conversion to 1T tracks and the source builder
+
+use std::error::Error;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::Path;
+use std::sync::{Arc, Condvar, Mutex, OnceLock};
+use std::time::Duration;
+use chrono::NaiveDate;
+use serde::Deserialize;
+use serde_json::Value;
+use onetagger_tagger::{LyricsLine, LyricsLinePart, Lyrics, Track, TrackNumber, AutotaggerSourceBuilder, PlatformInfo, TaggerConfig, AutotaggerSource, AudioFileInfo, PlatformCustomOptions, PlatformCustomOptionValue, FrameName, SupportedTag, supported_tags};
+use crate::{AppleMusic, AppleMusicError, Progress, SearchExtras};
+use crate::diagnostics::TrackDiagnostics;
+use crate::library::csv_row;
+use crate::models::*;
+use crate::matching::*;
+use crate::ttml::KaraokeLyrics;
+
+/// Upper limit of the threads option
+const MAX_THREADS: usize = 16;
+/// Default of the threads option
+const DEFAULT_THREADS: usize = 4;
+/// Artwork sizes to try if the full size is missing
+const ARTWORK_FALLBACK_SIZES: &[u64] = &[3000, 2000, 1400, 1000, 600];
+/// Artwork size of library uploads, their source size is unknown
+const LIBRARY_ARTWORK_SIZE: u64 = 3000;
+/// Rating tag value (0 - 100) of loved songs, 5 stars
+const LOVED_RATING: u8 = 100;
+/// Rating tag value (0 - 100) of disliked songs, 1 star
+const DISLIKED_RATING: u8 = 20;
+/// Genre ID of the generic "Music" genre
+const MUSIC_GENRE_ID: &'static str = "34";
+
+impl AppleMusic {
+    /// Get genres translated to the configured genres language
+    fn localized_genres(&self, song: &ItemMeta<SongAttributes>) -> Result<Vec<String>, Box<dyn Error>> {
+        let localized = self.song_in_language(&song.id, &self.config.genres_language)?;
+        Ok(self.config.genres.apply_localized(song, &localized))
+    }
+
+    /// Fill album level fields from the full album
+    fn apply_album(&self, track: &mut Track, album: &ItemMeta<AlbumAttributes>) {
+        track.album = Some(album.attributes.name.to_string());
+        if track.release_date.is_none() && track.release_year.is_none() {
+            if let Some(date) = album.attributes.release_date.as_ref() {
+                (track.release_date, track.release_year) = parse_release_date(date);
+            }
+        }
+        if let Some(track_count) = album.attributes.track_count {
+            track.track_total = Some(track_count);
+        }
+        if let Some(label) = album.label() {
+            track.label = Some(label);
+        }
+        // Keep classical attribution
+        if !self.config.classical_mode || track.album_artists.is_empty() {
+            track.album_artists = album.artists();
+        }
+        if let Some(upc) = album.attributes.upc.as_ref() {
+            set_tag(&mut track.other, FrameName::same("BARCODE"), vec![upc.to_string()]);
+        }
+        set_tag(&mut track.other, FrameName::same("RELEASETYPE"), vec![album.release_type().to_string()]);
+        if let Some(disc_total) = album.disc_total() {
+            set_tag(&mut track.other, FrameName::same("DISCTOTAL"), vec![disc_total.to_string()]);
+        }
+        if album.compilation() {
+            set_tag(&mut track.other, FrameName::new("TCMP", "COMPILATION", "cpil"), vec!["1".to_string()]);
+        }
+        if let (Some(url), true) = (artwork_url(album.attributes.artwork.as_ref(), &self.config), self.config.album_artwork) {
+            track.art = Some(url);
+        }
+        if let Some(video) = album.attributes.editorial_video.as_ref().map(|v| v.square()).flatten() {
+            set_tag(&mut track.other, FrameName::same("APPLE_ANIMATED_ARTWORK"), vec![video.to_string()]);
+        }
+        if let Some(copyright) = album.attributes.copyright.as_ref() {
+            track.other.push((FrameName::new("TCOP", "COPYRIGHT", "cprt"), vec![copyright.to_string()]));
+        }
+        // Editorial notes
+        let notes = album.attributes.editorial_notes.as_ref().map(|n| n.standard.as_ref().or(n.short.as_ref())).flatten();
+        if let Some(notes) = notes {
+            let frame = match self.config.editorial_notes {
+                EditorialNotesTag::Disabled => None,
+                EditorialNotesTag::Comment => Some(FrameName::new("COMM", "COMMENT", "©cmt")),
+                EditorialNotesTag::AlbumDescription => Some(FrameName::same("ALBUM_DESCRIPTION")),
+            };
+            if let Some(frame) = frame {
+                track.other.push((frame, vec![html_to_text(notes)]));
+            }
+        }
+    }
+
+    /// Add artist info custom tags
+    fn apply_artist(track: &mut Track, artist: &ItemMeta<ArtistAttributes>) {
+        if let Some(bio) = artist.attributes.artist_bio.as_ref() {
+            track.other.push((FrameName::same("APPLE_ARTIST_BIO"), vec![html_to_text(bio)]));
+        }
+        if let Some(origin) = artist.attributes.origin.as_ref() {
+            track.other.push((FrameName::same("APPLE_ARTIST_ORIGIN"), vec![origin.to_string()]));
+        }
+        if let Some(born_or_formed) = artist.attributes.born_or_formed.as_ref() {
+            track.other.push((FrameName::same("APPLE_ARTIST_BORN_FORMED"), vec![born_or_formed.to_string()]));
+        }
+    }
+
+    /// Write artist image URL tag and download `artist.jpg` into the artist folder (parent of album folder)
+    fn apply_artist_image(&self, track: &mut Track, path: &Path, artist: &ItemMeta<ArtistAttributes>) {
+        let artwork = match (self.config.artist_image, artist.attributes.artwork.as_ref()) {
+            (ArtistImage::Disabled, _) | (_, None) => return,
+            (_, Some(artwork)) => artwork,
+        };
+        let url = artwork.url_for(artwork.width, artwork.height, ArtworkFormat::Jpg, ArtworkCrop::Square);
+        set_tag(&mut track.other, FrameName::same("APPLE_ARTIST_IMAGE"), vec![url.to_string()]);
+        if self.config.artist_image != ArtistImage::Download {
+            return;
+        }
+        let album_folder = match path.parent() {
+            Some(folder) => folder,
+            None => return,
+        };
+        let target = album_folder.parent().unwrap_or(album_folder).join("artist.jpg");
+        if target.exists() {
+            return;
+        }
+        match self.download_artwork(artwork, artwork.width.max(artwork.height), ArtworkFormat::Jpg) {
+            Ok(data) => if let Err(e) = std::fs::write(&target, data) {
+                warn!("Failed writing artist image: {e}");
+            },
+            Err(e) => warn!("Failed downloading artist image: {e}"),
+        }
+    }
+
+    /// Write media center NFO into album folder, once per run and only if it doesn't exist yet
+    fn write_album_nfo(&self, album: &ItemMeta<AlbumAttributes>, path: &Path) {
+        if !self.covers.lock().unwrap().insert(path.to_path_buf()) || path.exists() {
+            return;
+        }
+        let thumb = artwork_url(album.attributes.artwork.as_ref(), &self.config);
+        let review = album.attributes.editorial_notes.as_ref().map(|n| n.standard.as_ref().or(n.short.as_ref())).flatten().map(|n| html_to_text(n));
+        debug!("Writing NFO to: {}", path.display());
+        if let Err(e) = std::fs::write(path, crate::nfo::album_nfo(album, thumb.as_deref(), review.as_deref())) {
+            warn!("Failed writing NFO: {e}");
+        }
+    }
+
+    /// Write album report JSON into album folder, once per run
+    fn write_album_report(&self, album: &ItemMeta<AlbumAttributes>, path: &Path) {
+        if !self.covers.lock().unwrap().insert(path.to_path_buf()) {
+            return;
+        }
+        let write = || -> Result<(), Box<dyn Error>> {
+            std::fs::write(path, serde_json::to_string_pretty(&crate::AlbumReport::new(album))?)?;
+            Ok(())
+        };
+        if let Err(e) = write() {
+            warn!("Failed writing album report: {e}");
+        }
+    }
+
+    /// Write artwork to file, once per path and only if it doesn't exist yet
+    fn write_cover(&self, url: &str, path: &Path) {
+        if !self.covers.lock().unwrap().insert(path.to_path_buf()) || path.exists() {
+            return;
+        }
+        debug!("Writing cover to: {}", path.display());
+        match self.download_image(url) {
+            Ok(data) => if let Err(e) = std::fs::write(path, data) {
+                warn!("Failed writing cover: {e}");
+            },
+            Err(e) => warn!("Failed downloading cover: {e}"),
+        }
+    }
+
+    /// Check artwork URL with HEAD request, stepping down through `ARTWORK_FALLBACK_SIZES` if missing
+    fn valid_artwork_url(&self, url: String) -> String {
+        let exists = |url: &str| self.client.head(url).send().map(|r| r.status().is_success()).unwrap_or(false);
+        if exists(&url) {
+            return url;
+        }
+        for size in ARTWORK_FALLBACK_SIZES {
+            if let Some(smaller) = resize_artwork_url(&url, *size) {
+                if exists(&smaller) {
+                    debug!("Using smaller artwork: {smaller}");
+                    return smaller;
+                }
+            }
+        }
+        warn!("Artwork not available: {url}");
+        url
+    }
+
+    /// Render comment template, available: `{storefront}`, `{date}`, `{id}`, `{album_id}`, `{isrc}`, `{url}`
+    fn render_comment(&self, template: &str, track: &Track) -> String {
+        let storefront = self.catalog.lock().unwrap().clone().unwrap_or_default();
+        template
+            .replace("{storefront}", &storefront.to_uppercase())
+            .replace("{date}", &chrono::Local::now().format("%Y-%m-%d").to_string())
+            .replace("{id}", track.track_id.as_deref().unwrap_or_default())
+            .replace("{album_id}", &track.release_id)
+            .replace("{isrc}", track.isrc.as_deref().unwrap_or_default())
+            .replace("{url}", &track.url)
+    }
+
+    /// Map credit roles to tags
+    fn credits_tags(credits: &[CreditCategory]) -> Vec<(FrameName, Vec<String>)> {
+        let mut tags: Vec<(FrameName, Vec<String>)> = vec![];
+        let mut add = |frame: FrameName, value: String| {
+            match tags.iter_mut().find(|(f, _)| f == &frame) {
+                Some((_, values)) => if !values.contains(&value) { values.push(value) },
+                None => tags.push((frame, vec![value])),
+            }
+        };
+        for category in credits {
+            let artists = category.relationships.as_ref().map(|r| r.credit_artists.as_ref()).flatten();
+            for artist in artists.map(|a| a.data.iter()).into_iter().flatten() {
+                let name = artist.attributes.name.to_string();
+                // Performers with their role
+                if category.attributes.kind.as_deref() == Some("performer") {
+                    for role in &artist.attributes.role_names {
+                        add(FrameName::same("PERFORMER"), format!("{name} ({role})"));
+                    }
+                    continue;
+                }
+                for role in artist.attributes.role_names.iter().map(|r| r.to_lowercase()) {
+                    if role.contains("producer") {
+                        add(FrameName::same("PRODUCER"), name.clone());
+                    } else if role.contains("mixing") {
+                        add(FrameName::same("MIXER"), name.clone());
+                    } else if role.contains("engineer") {
+                        add(FrameName::same("ENGINEER"), name.clone());
+                    } else if role.contains("lyrics") || role.contains("lyricist") {
+                        add(FrameName::new("TEXT", "LYRICIST", "LYRICIST"), name.clone());
+                    } else if role.contains("arranger") {
+                        add(FrameName::same("ARRANGER"), name.clone());
+                    }
+                }
+            }
+        }
+        tags
+    }
+
+    /// Get ID of the song to fetch lyrics from, explicit equivalent for clean songs if enabled
+    fn lyrics_song_id(&self, song: &ItemMeta<SongAttributes>) -> String {
+        if self.config.explicit_lyrics && song.attributes.explicit() == Some(false) {
+            match self.equivalents(&song.id) {
+                Ok(songs) => {
+                    if let Some(explicit) = songs.into_iter().find(|s| s.id != song.id && s.attributes.explicit() == Some(true)) {
+                        debug!("Using lyrics from explicit version: {}", explicit.id);
+                        return explicit.id;
+                    }
+                },
+                Err(e) => warn!("Failed getting explicit equivalent: {e}"),
+            }
+        }
+        song.id.to_string()
+    }
+
+    /// Build track from matched song and fetch the extra data
+    fn enrich(&self, info: &AudioFileInfo, config: &TaggerConfig, song: &ItemMeta<SongAttributes>, accuracy: f64) -> Track {
+        // Library uploads without catalog version only have the library metadata and artwork
+        let catalog = !song.id.starts_with("i.");
+        if !catalog {
+            debug!("Library song {} isn't in catalog, using library metadata", song.id);
+        }
+        // Lyrics, not for matches below threshold which get discarded
+        let lyrics_enabled = config.synced_lyrics || config.unsynced_lyrics;
+        if lyrics_enabled && accuracy < config.strictness {
+            debug!("Skipping lyrics, accuracy {accuracy:.2} is below strictness {:.2}", config.strictness);
+        }
+        let fetch_lyrics = lyrics_enabled && catalog && accuracy >= config.strictness;
+        std::thread::scope(|scope| {
+            // Independent of the album and other data, so fetched alongside them
+            let lyrics = fetch_lyrics.then(|| scope.spawn(|| {
+                crate::diagnostics::start(&info.path);
+                let lyrics = self.karaoke_lyrics_in_language(&self.lyrics_song_id(song), &self.lyrics_language()).map_err(|e| e.to_string());
+                (lyrics, crate::diagnostics::finish())
+            }));
+            self.enrich_track(info, config, song, catalog, lyrics)
+        })
+    }
+
+    /// Enrich track with everything except lyrics, which are joined from the fetching thread
+    fn enrich_track(&self, info: &AudioFileInfo, config: &TaggerConfig, song: &ItemMeta<SongAttributes>, catalog: bool, lyrics: Option<LyricsFetch>) -> Track {
+        let mut track = song.clone().to_track(&self.config);
+        // Skip requests whose output wouldn't be written, sidecar has everything
+        let sidecar = self.config.metadata_sidecar != MetadataSidecar::Disabled;
+        let mut export = serde_json::Map::new();
+        export.insert("song".to_string(), serde_json::to_value(song).unwrap_or_default());
+        let comment = !self.config.comment_template.is_empty();
+        let album_needed = config.label || config.track_total || config.album_artist || config.release_date || config.other_tags || comment
+            || (config.album_art && self.config.album_artwork) || self.config.album_images || self.config.animated_artwork || self.config.album_nfo || self.config.album_report || sidecar;
+        let artist_needed = config.other_tags || comment || self.config.artist_image == ArtistImage::Download || sidecar;
+        // Fetch full album
+        if self.config.fetch_album && album_needed && catalog {
+            // Relationship can be missing, album ID is also in the URL
+            if track.release_id.is_empty() {
+                track.release_id = album_id_from_url(&track.url).unwrap_or_default();
+            }
+            if !track.release_id.is_empty() {
+                match self.shared_album(&track.release_id) {
+                    Ok(album) => {
+                        self.apply_album(&mut track, &album);
+                        export.insert("album".to_string(), serde_json::to_value(album.as_ref()).unwrap_or_default());
+                        if let (Some(folder), true) = (info.path.parent(), self.config.album_nfo) {
+                            self.write_album_nfo(&album, &folder.join("album.nfo"));
+                        }
+                        if let (Some(folder), true) = (info.path.parent(), self.config.album_report) {
+                            self.write_album_report(&album, &folder.join("album.json"));
+                        }
+                        // Additional album images
+                        if let (Some(images), Some(folder), true) = (album.attributes.editorial_artwork.as_ref(), info.path.parent(), self.config.album_images) {
+                            for (kind, artwork) in images {
+                                let url = artwork.url_for(artwork.width, artwork.height, ArtworkFormat::Jpg, ArtworkCrop::Square);
+                                self.write_cover(&url, &folder.join(format!("{kind}.jpg")));
+                            }
+                        }
+                        // Motion artwork sidecar
+                        let video = album.attributes.editorial_video.as_ref().map(|v| v.square()).flatten();
+                        if let (Some(video), true) = (video, self.config.animated_artwork) {
+                            if let Err(e) = self.download_hls(video, &info.path.with_extension("cover.mp4")) {
+                                warn!("Failed downloading animated artwork: {e}");
+                            }
+                        }
+                    },
+                    Err(e) => warn!("Failed getting album: {e}"),
+                }
+            }
+        }
+        // Translate genres
+        if config.genre && catalog && !self.config.genres_language.is_empty() && self.config.genres_language != self.language {
+            match self.localized_genres(song) {
+                Ok(genres) => track.genres = genres,
+                Err(e) => warn!("Failed getting localized genres: {e}"),
+            }
+        }
+        // Map genres
+        if !self.config.genre_map.is_empty() {
+            let mut genres: Vec<String> = vec![];
+            for genre in track.genres.drain(..) {
+                let genre = self.config.genre_map.get(&genre).cloned().unwrap_or(genre);
+                if !genres.contains(&genre) {
+                    genres.push(genre);
+                }
+            }
+            track.genres = genres;
+        }
+        // Download preview
+        if self.config.download_preview && catalog {
+            match self.download_preview(song) {
+                Ok(data) => if let Err(e) = std::fs::write(info.path.with_extension("preview.m4a"), data) {
+                    warn!("Failed writing preview: {e}");
+                },
+                Err(e) => warn!("Failed downloading preview: {e}"),
+            }
+        }
+        // Primary artist info / image
+        if artist_needed && catalog && (self.config.artist_info || self.config.artist_image != ArtistImage::Disabled || sidecar) {
+            let artist_id = song.relationships.as_ref().map(|r| r.artists.as_ref()).flatten().map(|a| a.data.first()).flatten().map(|a| a.id.to_string());
+            if let Some(artist_id) = artist_id {
+                match self.get_artist(&artist_id) {
+                    Ok(artist) => {
+                        export.insert("artist".to_string(), serde_json::to_value(&artist).unwrap_or_default());
+                        if self.config.artist_info {
+                            Self::apply_artist(&mut track, &artist);
+                        }
+                        self.apply_artist_image(&mut track, &info.path, &artist);
+                    },
+                    Err(e) => warn!("Failed getting artist: {e}"),
+                }
+            }
+        }
+        // Fetch credits
+        let credits_needed = self.config.credits && (config.other_tags || comment);
+        if (credits_needed || sidecar) && catalog {
+            match self.credits(&song.id) {
+                Ok(credits) => {
+                    export.insert("credits".to_string(), serde_json::to_value(&credits).unwrap_or_default());
+                    if credits_needed {
+                        track.other.extend(Self::credits_tags(&credits));
+                    }
+                },
+                Err(e) => warn!("Failed getting credits: {e}"),
+            }
+        }
+        // Comment template
+        if !self.config.comment_template.is_empty() {
+            let comment = self.render_comment(&self.config.comment_template, &track);
+            let frame = FrameName::new("COMM", "COMMENT", "©cmt");
+            match track.other.iter_mut().find(|(f, _)| f == &frame) {
+                Some((_, values)) => values.push(comment),
+                None => track.other.push((frame, vec![comment])),
+            }
+        }
+        // Loved / disliked in Apple Music, on the common 0 - 100 scale
+        if self.config.library_ratings && config.other_tags {
+            match self.song_rating(&song.id) {
+                Ok(Some(rating)) => {
+                    let rating = if rating > 0 { LOVED_RATING } else { DISLIKED_RATING };
+                    set_tag(&mut track.other, FrameName::new("RATING", "RATING", "rate"), vec![rating.to_string()]);
+                },
+                Ok(None) => {},
+                Err(e) => warn!("Failed getting rating: {e}"),
+            }
+        }
+        // Listening stats of the library version
+        if self.config.play_counts && config.other_tags {
+            match self.library_song(&song.id) {
+                Ok(Some(library)) => {
+                    if let Some(count) = library.attributes.extra.get("playCount").map(|c| c.as_u64()).flatten() {
+                        set_tag(&mut track.other, FrameName::same("APPLE_PLAY_COUNT"), vec![count.to_string()]);
+                    }
+                    if let Some(date) = library.attributes.extra.get("lastPlayedDate").map(|d| d.as_str()).flatten() {
+                        set_tag(&mut track.other, FrameName::same("APPLE_LAST_PLAYED"), vec![date.to_string()]);
+                    }
+                },
+                Ok(None) => debug!("Song {} isn't in library", song.id),
+                Err(e) => warn!("Failed getting library song: {e}"),
+            }
+        }
+        // Universal link
+        if self.config.song_link && config.other_tags && !track.url.is_empty() {
+            match self.song_link(&track.url) {
+                Ok(url) => set_tag(&mut track.other, FrameName::same("SONGLINK_URL"), vec![url]),
+                Err(e) => warn!("Failed resolving song.link URL: {e}"),
+            }
+        }
+        // Other platforms' IDs
+        if self.config.platform_ids && config.other_tags {
+            if let Some(isrc) = song.attributes.isrc.as_ref().filter(|i| !i.is_empty()) {
+                match self.deezer_id(isrc) {
+                    Ok(Some(id)) => set_tag(&mut track.other, FrameName::same("DEEZER_TRACK_ID"), vec![id]),
+                    Ok(None) => {},
+                    Err(e) => warn!("Deezer lookup failed: {e}"),
+                }
+                let (client_id, client_secret) = (self.config.spotify_client_id.trim(), self.config.spotify_client_secret.trim());
+                if !client_id.is_empty() && !client_secret.is_empty() {
+                    match self.spotify_id(isrc, client_id, client_secret) {
+                        Ok(Some(id)) => set_tag(&mut track.other, FrameName::same("SPOTIFY_TRACK_ID"), vec![id]),
+                        Ok(None) => {},
+                        Err(e) => warn!("Spotify lookup failed: {e}"),
+                    }
+                }
+            }
+        }
+        // Picard compatible MusicBrainz IDs
+        #[cfg(feature = "musicbrainz")]
+        if self.config.musicbrainz_ids && config.other_tags {
+            if let Some(isrc) = song.attributes.isrc.as_ref().filter(|i| !i.is_empty()) {
+                match self.musicbrainz_ids(isrc, &song.attributes.album_name) {
+                    Ok(Some(ids)) => {
+                        // Recording MBID, Picard keeps it in UFID which isn't writable as a text frame
+                        set_tag(&mut track.other, FrameName::new("MusicBrainz Recording Id", "MUSICBRAINZ_TRACKID", "MusicBrainz Track Id"), vec![ids.recording]);
+                        if let Some(release) = ids.release {
+                            set_tag(&mut track.other, FrameName::new("MusicBrainz Album Id", "MUSICBRAINZ_ALBUMID", "MusicBrainz Album Id"), vec![release]);
+                        }
+                        if let Some(group) = ids.release_group {
+                            set_tag(&mut track.other, FrameName::new("MusicBrainz Release Group Id", "MUSICBRAINZ_RELEASEGROUPID", "MusicBrainz Release Group Id"), vec![group]);
+                        }
+                    },
+                    Ok(None) => debug!("ISRC {isrc} not on MusicBrainz"),
+                    Err(e) => warn!("MusicBrainz lookup failed: {e}"),
+                }
+            }
+        }
+        if let Some(fetch) = lyrics {
+            let (lyrics, diagnostics) = fetch.join().unwrap_or_else(|_| (Err("Lyrics thread panicked".to_string()), None));
+            crate::diagnostics::merge(diagnostics);
+            match lyrics {
+                Ok(lyrics) => {
+                    // Karaoke sidecar
+                    if self.config.karaoke_json && lyrics.synced() {
+                        if let Err(e) = Self::write_karaoke_json(&info.path, &lyrics) {
+                            warn!("Failed writing karaoke JSON: {e}");
+                        }
+                    }
+                    let mut lyrics = lyrics.into();
+                    if self.config.lyrics_wrap > 0 {
+                        lyrics = Self::wrap_lyrics(lyrics, self.config.lyrics_wrap as usize);
+                    }
+                    track.lyrics = Some(lyrics);
+                },
+                Err(e) => warn!("Failed getting lyrics: {e}"),
+            }
+        }
+        // Step down to smaller artwork if the URL doesn't exist
+        if self.config.validate_artwork {
+            if let Some(art) = track.art.take() {
+                track.art = Some(self.valid_artwork_url(art));
+            }
+        }
+        // Folder artwork
+        if !self.config.cover_file.trim().is_empty() {
+            if let (Some(art), Some(folder)) = (track.art.as_ref(), info.path.parent()) {
+                self.write_cover(art, &folder.join(self.config.cover_file.trim()));
+            }
+        }
+        if sidecar {
+            export.insert("track".to_string(), serde_json::to_value(&track).unwrap_or_default());
+            if let Err(e) = self.export_metadata(&info.path, Value::Object(export)) {
+                warn!("Failed writing metadata sidecar: {e}");
+            }
+        }
+        // Frames of tags not selected in the tagger
+        if !config.composer {
+            track.other.retain(|(f, _)| f.id3 != "TCOM");
+        }
+        if !config.barcode {
+            track.other.retain(|(f, _)| f.id3 != "BARCODE");
+        }
+        track
+    }
+
+    /// Write everything fetched for file into `<file>.apple.json` or add row to the run CSV
+    fn export_metadata(&self, path: &Path, metadata: Value) -> Result<(), Box<dyn Error>> {
+        match self.config.metadata_sidecar {
+            MetadataSidecar::Disabled => Ok(()),
+            MetadataSidecar::Json => {
+                std::fs::write(path.with_extension("apple.json"), serde_json::to_string_pretty(&metadata)?)?;
+                Ok(())
+            },
+            MetadataSidecar::Csv => {
+                let csv_path = self.config.metadata_csv_file.trim();
+                if csv_path.is_empty() {
+                    return Err("Missing metadata CSV file".into());
+                }
+                let mut row = BTreeMap::new();
+                flatten_json("", &metadata, &mut row);
+                row.insert("path".to_string(), path.to_string_lossy().to_string());
+                let mut rows = self.metadata_rows.lock().unwrap();
+                rows.push(row);
+                // Columns are union of all rows, so the whole file is rewritten
+                let columns: BTreeSet<&str> = rows.iter().flat_map(|r| r.keys().map(|k| k.as_str())).filter(|k| *k != "path").collect();
+                let columns: Vec<&str> = std::iter::once("path").chain(columns).collect();
+                let mut out = csv_row(&columns);
+                for row in rows.iter() {
+                    out.push_str(&csv_row(&columns.iter().map(|c| row.get(*c).map(|v| v.as_str()).unwrap_or_default()).collect::<Vec<_>>()));
+                }
+                std::fs::write(csv_path, out)?;
+                Ok(())
+            }
+        }
+    }
+
+    /// Dry run: match and enrich like the tagger, but without writing any files or run state.
+    /// Returns accuracy and the Track serialized to JSON
+    pub fn preview(&self, info: &AudioFileInfo, config: &TaggerConfig) -> Result<Option<(f64, Value)>, Box<dyn Error>> {
+        let mut am = self.clone();
+        am.config.download_preview = false;
+        am.config.animated_artwork = false;
+        am.config.album_images = false;
+        am.config.karaoke_json = false;
+        am.config.add_to_library = false;
+        am.config.playlist = String::new();
+        am.config.cover_file = String::new();
+        am.config.album_nfo = false;
+        am.config.album_report = false;
+        am.config.post_match_command = String::new();
+        am.config.metadata_sidecar = MetadataSidecar::Disabled;
+        am.config.diagnostics_file = String::new();
+        if am.config.artist_image == ArtistImage::Download {
+            am.config.artist_image = ArtistImage::Tag;
+        }
+        // Don't leak album context into the real run
+        am.album_context = Arc::new(Mutex::new(self.album_context.lock().unwrap().clone()));
+        am.covers = Default::default();
+        am.diagnostics = Arc::new(Mutex::new(vec![]));
+        // Folder counts as prefetched, so the batch lookup doesn't fill the shared song cache
+        let folder = info.path.parent().map(|p| p.to_path_buf()).unwrap_or_default();
+        am.prefetched = Arc::new(Mutex::new(HashMap::from([(folder, Arc::new(Mutex::new(true)))])));
+        match am.match_track(info, config)? {
+            Some((accuracy, track)) => Ok(Some((accuracy, serde_json::to_value(&track)?))),
+            None => Ok(None)
+        }
+    }
+
+    /// Get the lyrics
+    pub fn lyrics(&self, song_id: &str) -> Result<Lyrics, Box<dyn Error>> {
+        Ok(self.karaoke_lyrics_in_language(song_id, &self.lyrics_language())?.into())
+    }
+
+    /// Configured lyrics language, falls back to metadata language
+    fn lyrics_language(&self) -> String {
+        match self.config.lyrics_language.trim() {
+            "" => self.language.clone(),
+            l => l.replace('-', "_")
+        }
+    }
+
+    /// Re-wrap unsynced lines to max `width` characters, synced lines are kept as is
+    fn wrap_lyrics(mut lyrics: Lyrics, width: usize) -> Lyrics {
+        for paragraph in lyrics.paragraphs.iter_mut() {
+            let mut wrapped = vec![];
+            for line in paragraph.drain(..) {
+                if line.start.is_some() || line.text.chars().count() <= width {
+                    wrapped.push(line);
+                    continue;
+                }
+                // Split by words
+                let mut text = String::new();
+                for word in line.text.split_whitespace() {
+                    if !text.is_empty() && text.chars().count() + word.chars().count() + 1 > width {
+                        wrapped.push(LyricsLine { text: std::mem::take(&mut text), start: None, end: None, parts: vec![] });
+                    }
+                    if !text.is_empty() {
+                        text.push(' ');
+                    }
+                    text.push_str(word);
+                }
+                if !text.is_empty() {
+                    wrapped.push(LyricsLine { text, start: None, end: None, parts: vec![] });
+                }
+            }
+            *paragraph = wrapped;
+        }
+        lyrics
+    }
+
+    /// Write karaoke lyrics as JSON sidecar next to the audio file
+    fn write_karaoke_json(path: &Path, lyrics: &KaraokeLyrics) -> Result<(), Box<dyn Error>> {
+        let path = path.with_extension("karaoke.json");
+        debug!("Writing karaoke JSON to: {}", path.display());
+        std::fs::write(path, serde_json::to_string(lyrics)?)?;
+        Ok(())
+    }
+}
+
+impl AppleMusic {
+    /// Append song to the `playlist` of this run, resolved by ID or name and created if missing
+    fn append_to_run_playlist(&self, song_id: &str) -> Result<(), Box<dyn Error>> {
+        let mut playlist = self.playlist.lock().unwrap();
+        if playlist.is_none() {
+            let name = self.config.playlist.trim().replace("{date}", &chrono::Local::now().format("%Y-%m-%d").to_string());
+            let id = match name.starts_with("p.") {
+                true => name,
+                false => match self.library_playlists()?.into_iter().find(|p| p.attributes.name == name) {
+                    Some(existing) => existing.id,
+                    None => {
+                        info!("Creating playlist: {name}");
+                        self.create_playlist(&name, "Songs matched by OneTagger")?
+                    }
+                }
+            };
+            *playlist = Some(id);
+        }
+        self.add_to_playlist(playlist.as_ref().unwrap(), &[song_id])
+    }
+
+    /// Run `post_match_command` in shell with Track JSON on stdin and file path in `ONETAGGER_PATH`.
+    /// Tags are written by OneTagger after it finishes.
+    fn run_post_match_command(&self, info: &AudioFileInfo, track: &Track) -> Result<(), Box<dyn Error>> {
+        let command = self.config.post_match_command.trim();
+        let (shell, flag) = match cfg!(windows) {
+            true => ("cmd", "/C"),
+            false => ("sh", "-c")
+        };
+        let json = serde_json::to_vec(track)?;
+        let mut child = std::process::Command::new(shell)
+            .arg(flag)
+            .arg(command)
+            .env("ONETAGGER_PATH", &info.path)
+            .stdin(std::process::Stdio::piped())
+            .spawn()?;
+        // Dropping stdin closes it, so the command sees EOF
+        if let Some(mut stdin) = child.stdin.take() {
+            // Command can exit without reading stdin, it still has to be waited for
+            if let Err(e) = std::io::Write::write_all(&mut stdin, &json) {
+                warn!("Failed writing track JSON to {command}: {e}");
+            }
+        }
+        let status = child.wait()?;
+        if !status.success() {
+            return Err(format!("{command} exited with {status}").into());
+        }
+        Ok(())
+    }
+
+    /// Add diagnostics of a track and rewrite the run report
+    fn save_diagnostics(&self, diagnostics: TrackDiagnostics) {
+        let mut all = self.diagnostics.lock().unwrap();
+        all.push(diagnostics);
+        let path = self.config.diagnostics_file.trim();
+        let write = || -> Result<(), Box<dyn Error>> {
+            std::fs::write(path, serde_json::to_string_pretty(&*all)?)?;
+            Ok(())
+        };
+        if let Err(e) = write() {
+            warn!("Failed writing diagnostics report to {path}: {e}");
+        }
+    }
+
+    /// Match file and build the track
+    fn match_file(&mut self, info: &AudioFileInfo, config: &TaggerConfig) -> Result<Option<(f64, Track)>, Box<dyn Error>> {
+        if self.config.skip_tagged && self.already_tagged(info) {
+            info!("Skipping already tagged file: {:?}", info.path);
+            return Ok(None);
+        }
+        let info = &self.complete_info(info);
+        // Platform specific threshold
+        let mut config = config.clone();
+        if self.config.strictness > 0 {
+            config.strictness = self.config.strictness as f64 / 100.0;
+        }
+        let config = &config;
+        // Matching stage, the slot is released before enrichment so other files can search meanwhile
+        let matching = WorkerSlot::acquire(&self.workers, self.config.threads.clamp(1, MAX_THREADS));
+        let mut matched = self.find_match(info, config)?.map(|c| (self.clone(), c));
+        // Retry in other storefronts, extra data has to be fetched from the same one
+        if matched.is_none() {
+            for storefront in self.config.fallback_storefronts.split(',').map(|s| s.trim().to_lowercase()).filter(|s| !s.is_empty()) {
+                let am = self.with_storefront(&storefront);
+                match am.find_match(info, config) {
+                    Ok(Some(candidate)) => {
+                        debug!("Matched in storefront: {storefront}");
+                        matched = Some((am, candidate));
+                        break;
+                    },
+                    Ok(None) => {},
+                    // Batch wide failures are reported to the host
+                    Err(e) if matches!(e.downcast_ref(), Some(AppleMusicError::RateLimited { .. } | AppleMusicError::AuthExpired)) => return Err(e),
+                    Err(e) => warn!("Search in storefront {storefront} failed: {e}"),
+                }
+            }
+        }
+        drop(matching);
+        if let Some((am, candidate)) = matched {
+            let _enriching = WorkerSlot::acquire(&self.enrich_workers, self.config.enrich_threads.clamp(1, MAX_THREADS));
+            if self.config.album_context || self.config.album_mode {
+                // Library albums can't be looked up in catalog
+                if let Some(album_id) = candidate.song.album_id().filter(|id| !id.starts_with("l.")) {
+                    self.album_context.lock().unwrap().insert(Self::album_context_key(info), album_id);
+                }
+            }
+            let mut track = am.enrich(info, config, &candidate.song, candidate.accuracy);
+            if self.config.skip_tagged {
+                set_tag(&mut track.other, FrameName::same(TAGGED_DATE_TAG), vec![chrono::Local::now().format("%Y-%m-%d").to_string()]);
+            }
+            if self.config.match_report {
+                set_tag(&mut track.other, FrameName::same("APPLE_MATCH_REPORT"), vec![candidate.report(info)]);
+            }
+            // Mirror confident catalog matches to user library, library songs have `i.` IDs
+            let catalog_song = candidate.song.id.chars().all(|c| c.is_ascii_digit());
+            if self.config.add_to_library && candidate.accuracy >= config.strictness && catalog_song {
+                match am.add_to_library(&[&candidate.song.id]) {
+                    Ok(()) => debug!("Added {} to library", candidate.song.id),
+                    Err(e) => warn!("Failed adding {} to library: {e}", candidate.song.id),
+                }
+            }
+            if !self.config.playlist.trim().is_empty() && catalog_song {
+                if let Err(e) = am.append_to_run_playlist(&candidate.song.id) {
+                    warn!("Failed adding {} to playlist: {e}", candidate.song.id);
+                }
+            }
+            if !self.config.post_match_command.trim().is_empty() {
+                if let Err(e) = self.run_post_match_command(info, &track) {
+                    warn!("Post match command failed: {e}");
+                }
+            }
+            crate::diagnostics::record(|d| {
+                d.song_id = Some(candidate.song.id.to_string());
+                d.accuracy = Some(candidate.accuracy);
+                d.score = Some(candidate.score);
+            });
+            return Ok(Some((candidate.accuracy, track)));
+        }
+        Ok(None)
+    }
+}
+
+impl AutotaggerSource for AppleMusic {
+    fn match_track(&mut self, info: &AudioFileInfo, config: &TaggerConfig) -> Result<Option<(f64, Track)>, Box<dyn Error>> {
+        self.report(Progress::Matching { path: info.path.clone() });
+        if self.config.diagnostics_file.trim().is_empty() {
+            return self.match_file(info, config);
+        }
+        crate::diagnostics::start(&info.path);
+        let result = self.match_file(info, config);
+        if let Some(mut diagnostics) = crate::diagnostics::finish() {
+            if let Err(e) = result.as_ref() {
+                diagnostics.error = Some(e.to_string());
+            }
+            self.save_diagnostics(diagnostics);
+        }
+        result
+    }
+}
+
+/// Lyrics fetched on a scoped thread during enrichment, with the thread's diagnostics
+type LyricsFetch<'scope> = std::thread::ScopedJoinHandle<'scope, (Result<KaraokeLyrics, String>, Option<TrackDiagnostics>)>;
+
+/// Held during a stage of a track (matching, enrichment), limits concurrency of the stage below the tagger's thread count
+struct WorkerSlot {
+    workers: Arc<(Mutex<usize>, Condvar)>,
+}
+
+impl WorkerSlot {
+    /// Wait until less than `max` tracks are being matched
+    fn acquire(workers: &Arc<(Mutex<usize>, Condvar)>, max: usize) -> WorkerSlot {
+        let (count, cvar) = &**workers;
+        let mut count = cvar.wait_while(count.lock().unwrap(), |c| *c >= max).unwrap();
+        *count += 1;
+        WorkerSlot { workers: workers.clone() }
+    }
+}
+
+impl Drop for WorkerSlot {
+    fn drop(&mut self) {
+        let (count, cvar) = &*self.workers;
+        *count.lock().unwrap() -= 1;
+        cvar.notify_one();
+    }
+}
+
+impl Into<Lyrics> for KaraokeLyrics {
+    fn into(self) -> Lyrics {
+        let paragraphs = self.paragraphs.into_iter().map(|p| p.into_iter().map(|line| LyricsLine {
+            text: line.text,
+            start: line.start.map(Duration::from_millis),
+            end: line.end.map(Duration::from_millis),
+            parts: line.words.into_iter().map(|w| LyricsLinePart {
+                text: w.text,
+                start: w.start.map(Duration::from_millis),
+                end: w.end.map(Duration::from_millis)
+            }).collect()
+        }).collect()).collect();
+        Lyrics { paragraphs, language: self.language }
+    }
+}
+
+impl ItemMeta<SongAttributes> {
+    /// Convert to 1T track using the custom config
+    pub(crate) fn to_track(self, config: &AppleMusicConfig) -> Track {
+        // Borrow only the relationship, so attributes can be moved into the track
+        let album = self.relationships.as_ref().map(|r| r.albums.as_ref()).flatten().map(|a| a.data.first()).flatten();
+        // Custom tags
+        let mut other = vec![
+            (FrameName::same("APPLE_SYNCED_LYRICS"), vec![self.attributes.has_time_synced_lyrics.to_string()]),
+        ];
+
+        let (release, original) = self.release(config);
+        if let Some(original) = original {
+            other.push((FrameName::new("TDOR", "ORIGINALDATE", "ORIGINALDATE"), vec![original.to_string()]));
+        }
+        let (release_date, release_year) = release.map(parse_release_date).unwrap_or((None, None));
+
+        if let Some(explicit) = self.attributes.explicit() {
+            other.push((FrameName::same("ITUNESADVISORY"), vec![if explicit { "1" } else { "2" }.to_string()]));
+        }
+        other.push((FrameName::same("APPLE_DIGITAL_MASTER"), vec![self.digital_master().to_string()]));
+        let variants = self.attributes.audio_variants();
+        if !variants.is_empty() {
+            other.push((FrameName::same("APPLE_AUDIO_VARIANTS"), variants));
+        }
+        let mut artist_urls: Vec<String> = self.attributes.artist_url.iter().cloned().collect();
+        if config.all_artist_urls {
+            for artist in self.relationships.as_ref().map(|r| r.artists.as_ref()).flatten().map(|a| a.data.iter()).into_iter().flatten() {
+                if !artist_urls.contains(&artist.attributes.url) {
+                    artist_urls.push(artist.attributes.url.to_string());
+                }
+            }
+        }
+        if !artist_urls.is_empty() {
+            other.push((FrameName::same("APPLE_ARTIST_URL"), artist_urls));
+        }
+        // Artwork colors
+        if let (true, Some(artwork)) = (config.artwork_colors, self.attributes.artwork.as_ref()) {
+            if let Some(color) = artwork.bg_color.as_ref() {
+                other.push((FrameName::same("APPLE_ART_BG_COLOR"), vec![format!("#{color}")]));
+            }
+            let text_colors: Vec<String> = [&artwork.text_color1, &artwork.text_color2, &artwork.text_color3, &artwork.text_color4]
+                .into_iter().flatten().map(|c| format!("#{c}")).collect();
+            if !text_colors.is_empty() {
+                other.push((FrameName::same("APPLE_ART_TEXT_COLOR"), text_colors));
+            }
+        }
+        // Catalog IDs
+        if config.catalog_ids {
+            if let Some(album) = album.as_ref() {
+                other.push((FrameName::same("APPLE_ALBUM_ID"), vec![album.id.to_string()]));
+            }
+            let artist_ids: Vec<String> = self.relationships.as_ref().map(|r| r.artists.as_ref()).flatten()
+                .map(|a| a.data.iter().map(|a| a.id.to_string()).collect())
+                .unwrap_or_default();
+            if !artist_ids.is_empty() {
+                other.push((FrameName::same("APPLE_ARTIST_ID"), artist_ids));
+            }
+        }
+        if let Some(language) = self.attributes.audio_locale.as_deref().map(language_name).flatten() {
+            other.push((FrameName::new("TLAN", "LANGUAGE", "LANGUAGE"), vec![language]));
+        }
+        if let Some(preview) = self.attributes.previews.first() {
+            other.push((FrameName::same("APPLE_PREVIEW_URL"), vec![preview.url.to_string()]));
+        }
+        // Composers, prefer entities over splitting the string
+        let composers = self.relationships.as_ref().map(|r| r.composers.as_ref()).flatten().filter(|c| !c.data.is_empty());
+        if let Some(composers) = composers {
+            other.push((FrameName::new("TCOM", "COMPOSER", "©wrt"), composers.data.iter().map(|c| c.attributes.name.to_string()).collect()));
+            other.push((FrameName::same("APPLE_COMPOSER_ID"), composers.data.iter().map(|c| c.id.to_string()).collect()));
+        } else if let Some(composer) = self.attributes.composer_name.as_ref() {
+            other.push((FrameName::new("TCOM", "COMPOSER", "©wrt"), split_composers(composer)));
+        }
+        // Classical
+        if let Some(work) = self.attributes.work_name.as_ref() {
+            other.push((FrameName::new("TIT1", "WORK", "©wrk"), vec![work.to_string()]));
+        }
+        if let Some(movement) = self.attributes.movement_name.as_ref() {
+            other.push((FrameName::new("MVNM", "MOVEMENTNAME", "©mvn"), vec![movement.to_string()]));
+        }
+        if let Some(number) = self.attributes.movement_number {
+            other.push((FrameName::new("MVIN", "MOVEMENT", "©mvi"), vec![number.to_string()]));
+        }
+        if let Some(count) = self.attributes.movement_count {
+            other.push((FrameName::new("MOVEMENTTOTAL", "MOVEMENTTOTAL", "©mvc"), vec![count.to_string()]));
+        }
+
+        if let Some(album) = album.as_ref() {
+            if let Some(upc) = album.attributes.upc.as_ref() {
+                other.push((FrameName::same("BARCODE"), vec![upc.to_string()]));
+            }
+            other.push((FrameName::same("RELEASETYPE"), vec![album.release_type().to_string()]));
+        }
+        if album.as_ref().map(|a| a.compilation()).unwrap_or(false) {
+            other.push((FrameName::new("TCMP", "COMPILATION", "cpil"), vec!["1".to_string()]));
+        }
+        // Artwork URLs in multiple sizes
+        if let (true, Some(artwork)) = (config.artwork_urls, self.attributes.artwork.as_ref()) {
+            for (name, size) in [("SMALL", 250), ("MEDIUM", 1000), ("ORIGINAL", artwork.width.max(artwork.height))] {
+                let (width, height) = artwork.fit(size);
+                let url = artwork.url_for(width, height, config.artwork_format, config.artwork_crop);
+                other.push((FrameName::same(&format!("APPLE_ARTWORK_{name}")), vec![url]));
+            }
+        }
+
+        // Raw attribute passthrough
+        if !config.raw_attributes.trim().is_empty() {
+            other.extend(raw_attributes(&self, &config.raw_attributes));
+        }
+
+        let artists = self.track_artists(config);
+
+        // Classical: composer forward naming from attribution
+        let mut album_artists = album.as_ref().map(|a| a.artists()).unwrap_or(vec![]);
+        if config.classical_mode {
+            if let Some(attribution) = self.attributes.attribution.as_ref() {
+                set_tag(&mut other, FrameName::new("TCOM", "COMPOSER", "©wrt"), split_composers(attribution));
+                album_artists = vec![attribution.to_string()];
+            }
+        }
+
+        let genres = config.genres.apply(&self);
+
+        // Editorial moods / activities
+        let editorial: Vec<String> = self.attributes.moods.iter().chain(self.attributes.activities.iter()).cloned().collect();
+        let (styles, mood) = match config.editorial_tags {
+            EditorialTagsTarget::Disabled => (vec![], None),
+            EditorialTagsTarget::Style => (editorial, None),
+            EditorialTagsTarget::Mood => (vec![], editorial.first().cloned()),
+        };
+
+        // Create track
+        Track {
+            platform: "apple_music".to_string(),
+            title: self.attributes.name,
+            artists,
+            album_artists,
+            album: Some(self.attributes.album_name).filter(|a| !a.is_empty()),
+            genres,
+            styles,
+            mood,
+            art: artwork_url(self.attributes.artwork.as_ref(), config),
+            url: self.attributes.url,
+            label: album.as_ref().map(|a| a.label()).flatten(),
+            catalog_number: match config.id_as_catalog_number {
+                true => Some(self.id.to_string()),
+                false => None
+            },
+            track_id: Some(self.id),
+            release_id: album.map(|a| a.id.to_string()).unwrap_or_default(),
+            duration: Duration::from_millis(self.attributes.duration_in_millis),
+            track_number: Some(self.attributes.track_number).filter(|n| *n > 0).map(TrackNumber::Number),
+            track_total: album.as_ref().map(|a| a.attributes.track_count).flatten(),
+            disc_number: Some(self.attributes.disc_number as u16).filter(|n| *n > 0),
+            isrc: self.attributes.isrc,
+            lyrics: None,
+            release_year: release_year,
+            release_date: release_date,
+            other,
+            ..Default::default()
+        }
+    }
+
+    /// Track with only the fields used for scoring candidates, without cloning the song
+    pub(crate) fn to_match_track(&self, config: &AppleMusicConfig) -> Track {
+        let (release_date, release_year) = self.release(config).0.map(parse_release_date).unwrap_or((None, None));
+        Track {
+            platform: "apple_music".to_string(),
+            title: self.attributes.name.to_string(),
+            artists: self.track_artists(config),
+            album: Some(self.attributes.album_name.to_string()).filter(|a| !a.is_empty()),
+            duration: Duration::from_millis(self.attributes.duration_in_millis),
+            release_year,
+            release_date,
+            ..Default::default()
+        }
+    }
+
+    /// Release date and original date if enabled, album date is used as release date and earliest as original date
+    fn release(&self, config: &AppleMusicConfig) -> (Option<&str>, Option<&str>) {
+        let release = self.attributes.release_date.as_deref();
+        let album_date = self.album().map(|a| a.attributes.release_date.as_deref()).flatten();
+        if config.original_release_date {
+            let original = [release, album_date].into_iter().flatten().min();
+            return (album_date.or(release), original);
+        }
+        // Undated songs
+        (release.or(album_date), None)
+    }
+
+    /// Artists from relationship unless using the combined artist
+    fn track_artists(&self, config: &AppleMusicConfig) -> Vec<String> {
+        match self.relationships.as_ref().map(|r| r.artists.as_ref()).flatten() {
+            Some(artists) if !config.single_artist && !artists.data.is_empty() => artists.data.iter().map(|a| a.attributes.name.to_string()).collect(),
+            _ => vec![self.attributes.artist_name.to_string()]
+        }
+    }
+}
+
+impl Into<Track> for ItemMeta<SongAttributes> {
+    fn into(self) -> Track {
+        self.to_track(&AppleMusicConfig::default())
+    }
+}
+
+/// Artwork URL with configured max size (keeping aspect ratio), format and crop.
+/// `None` if the artwork is smaller than `min_artwork_size`, so existing art is kept.
+fn artwork_url(artwork: Option<&AppleMusicArtwork>, config: &AppleMusicConfig) -> Option<String> {
+    let artwork = artwork?;
+    if artwork.known_size() && artwork.width.min(artwork.height) < config.min_artwork_size as u64 {
+        debug!("Artwork too small: {}x{}", artwork.width, artwork.height);
+        return None;
+    }
+    let (width, height) = match config.max_artwork_size.parse::<u64>().ok().filter(|m| *m > 0) {
+        Some(max) => artwork.fit(max),
+        None if !artwork.known_size() => artwork.fit(LIBRARY_ARTWORK_SIZE),
+        None => (artwork.width, artwork.height)
+    };
+    Some(artwork.url_for(width, height, config.artwork_format, config.artwork_crop))
+}
+
+/// Copy values at raw attribute paths (`attributes.composerName`, `relationships.albums.data.0.attributes.upc`)
+/// into custom tags, tag name is `path=TAG` or `APPLE_` + last path segment in upper snake case
+fn raw_attributes(song: &ItemMeta<SongAttributes>, paths: &str) -> Vec<(FrameName, Vec<String>)> {
+    let value = match serde_json::to_value(song) {
+        Ok(value) => value,
+        Err(e) => {
+            warn!("Failed serializing song {} for raw attributes: {e}", song.id);
+            return vec![];
+        }
+    };
+    paths.split(',').filter_map(|entry| {
+        let (path, tag) = match entry.split_once('=') {
+            Some((path, tag)) => (path.trim(), tag.trim().to_string()),
+            None => {
+                let path = entry.trim();
+                let name = path.rsplit('.').next().unwrap_or_default();
+                let mut tag = "APPLE_".to_string();
+                for (i, c) in name.chars().enumerate() {
+                    if c.is_uppercase() && i > 0 {
+                        tag.push('_');
+                    }
+                    tag.push(c.to_ascii_uppercase());
+                }
+                (path, tag)
+            }
+        };
+        if path.is_empty() || tag.is_empty() {
+            return None;
+        }
+        let values = match value.pointer(&format!("/{}", path.replace('.', "/")))? {
+            Value::Null => return None,
+            Value::String(s) => vec![s.to_string()],
+            Value::Array(a) => a.iter().map(|v| v.as_str().map(String::from).unwrap_or_else(|| v.to_string())).collect(),
+            v => vec![v.to_string()],
+        };
+        debug!("Raw attribute {path} = {values:?}");
+        Some((FrameName::same(&tag), values))
+    }).collect()
+}
+
+/// Downscale rendered artwork URL (`.../3000x3000bb.jpg`) to fit max size, `None` if already smaller
+fn resize_artwork_url(url: &str, max: u64) -> Option<String> {
+    static SIZE: OnceLock<regex::Regex> = OnceLock::new();
+    let re = SIZE.get_or_init(|| regex::Regex::new(r"/(\d+)x(\d+)([a-z\-\d]*\.\w+)$").unwrap());
+    let captures = re.captures(url)?;
+    let (width, height): (u64, u64) = (captures[1].parse().ok()?, captures[2].parse().ok()?);
+    if width.max(height) <= max {
+        return None;
+    }
+    let scale = max as f64 / width.max(height) as f64;
+    let size = format!("/{}x{}{}", (width as f64 * scale).round() as u64, (height as f64 * scale).round() as u64, &captures[3]);
+    Some(re.replace(url, size.as_str()).to_string())
+}
+
+/// Parse Apple's release date, which can be `YYYY`, `YYYY-MM` or `YYYY-MM-DD`.
+/// Year is always returned if available, date only on full precision.
+fn parse_release_date(date: &str) -> (Option<NaiveDate>, Option<i16>) {
+    let year = date.get(0..4).map(|y| y.parse().ok()).flatten();
+    match date.len() {
+        4 => (None, year),
+        // Month precision can't be stored in NaiveDate
+        7 => (None, NaiveDate::parse_from_str(&format!("{date}-01"), "%Y-%m-%d").ok().and(year)),
+        _ => (NaiveDate::parse_from_str(date, "%Y-%m-%d").ok(), year)
+    }
+}
+
+/// Add custom tag to Track.other, replacing existing values
+fn set_tag(other: &mut Vec<(FrameName, Vec<String>)>, frame: FrameName, values: Vec<String>) {
+    match other.iter_mut().find(|(f, _)| f == &frame) {
+        Some((_, v)) => *v = values,
+        None => other.push((frame, values)),
+    }
+}
+
+/// Convert Apple's HTML (editorial notes) to plain text
+fn html_to_text(html: &str) -> String {
+    static BREAK: OnceLock<regex::Regex> = OnceLock::new();
+    static TAG: OnceLock<regex::Regex> = OnceLock::new();
+    let text = BREAK.get_or_init(|| regex::Regex::new("(?i)<br\\s*/?>|</p>").unwrap()).replace_all(html, "\n");
+    let text = TAG.get_or_init(|| regex::Regex::new("<[^>]*>").unwrap()).replace_all(&text, "");
+    // `&amp;` last, so double encoded entities stay encoded once
+    text.replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&apos;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+        .trim()
+        .to_string()
+}
+
+/// Split Apple's combined composer string (`A, B & C`), `&` only with spaces around and `/` never,
+/// so names like `AC/DC` stay whole
+fn split_composers(composer: &str) -> Vec<String> {
+    composer.split(|c| c == ',' || c == ';')
+        .flat_map(|c| c.split(" & "))
+        .map(|c| c.trim().to_string())
+        .filter(|c| !c.is_empty())
+        .collect()
+}
+
+/// Flatten JSON into dotted keys, array items are indexed
+fn flatten_json(prefix: &str, value: &Value, out: &mut BTreeMap<String, String>) {
+    let key = |k: &str| match prefix.is_empty() {
+        true => k.to_string(),
+        false => format!("{prefix}.{k}")
+    };
+    match value {
+        Value::Object(map) => map.iter().for_each(|(k, v)| flatten_json(&key(k), v, out)),
+        Value::Array(items) => items.iter().enumerate().for_each(|(i, v)| flatten_json(&key(&i.to_string()), v, out)),
+        Value::Null => {},
+        Value::String(s) => { out.insert(prefix.to_string(), s.to_string()); },
+        v => { out.insert(prefix.to_string(), v.to_string()); },
+    }
+}
+
+/// Override options with the selected profile from `profiles` JSON
+fn apply_profile(mut custom: Value) -> Result<Value, Box<dyn Error>> {
+    let name = custom.get("profile").map(|p| p.as_str()).flatten().unwrap_or_default().trim().to_string();
+    if name.is_empty() {
+        return Ok(custom);
+    }
+    let profiles = custom.get("profiles").map(|p| p.as_str()).flatten().unwrap_or_default();
+    let profiles: HashMap<String, serde_json::Map<String, Value>> = serde_json::from_str(profiles)
+        .map_err(|e| format!("Invalid profiles JSON: {e}"))?;
+    let profile = profiles.get(&name).ok_or(format!("Profile `{name}` is not defined in profiles"))?;
+    info!("Using Apple Music profile: {name}");
+    let options = custom.as_object_mut().ok_or("Invalid Apple Music options")?;
+    for (key, value) in profile {
+        if key == "media_user_token" || key == "profile" || key == "profiles" {
+            continue;
+        }
+        options.insert(key.to_string(), value.clone());
+    }
+    Ok(custom)
+}
+
+impl AppleMusic {
+    /// Apply new config keeping token and caches, localized caches are dropped if language changed
+    fn reconfigure(&mut self, config: AppleMusicConfig, tagger: &TaggerConfig) {
+        let language = match config.language.trim() {
+            "" => "en_GB",
+            l => l
+        };
+        let previous = std::mem::replace(&mut self.language, String::new());
+        self.set_language(language);
+        if previous != self.language {
+            debug!("Apple Music language changed from {previous} to {}, clearing metadata cache", self.language);
+            self.clear_metadata_caches();
+        }
+        // Snippets are only used for scoring
+        self.options.lyric_highlights = config.lyrics_highlights;
+        // Leave out search result parts no enabled tag uses, sidecar and raw attributes need everything
+        self.options.search_extras = SearchExtras {
+            composers: tagger.other_tags,
+            record_labels: tagger.label,
+            editorial: config.editorial_tags != EditorialTagsTarget::Disabled,
+            audio_variants: tagger.other_tags,
+            all_attributes: !config.raw_attributes.trim().is_empty() || config.metadata_sidecar != MetadataSidecar::Disabled,
+        };
+        self.config = config;
+    }
+}
+
+/// 1T source builder
+pub struct AppleMusicBuilder {
+    apple_music: Option<AppleMusic>
+}
+
+impl AutotaggerSourceBuilder for AppleMusicBuilder {
+    fn new() -> Self {
+        AppleMusicBuilder {
+            apple_music: None
+        }
+    }
+
+    fn get_source(&mut self, config: &TaggerConfig) -> Result<Box<dyn AutotaggerSource>, Box<dyn Error>> {
+        let custom = config.custom.get("apple_music")
+            .ok_or("Apple Music options are missing: open the Apple Music platform options and set the Media User Token")?;
+        let custom = apply_profile(custom.to_owned())?;
+        let mut amc: AppleMusicConfig = serde_json::from_value(custom)
+            .map_err(|e| format!("Invalid Apple Music options ({e}): reset the Apple Music platform options"))?;
+        if amc.media_user_token.trim().is_empty() && amc.import_token {
+            match crate::import_media_user_tokens().into_iter().next() {
+                Some(imported) => {
+                    info!("Using Media User Token from {}", imported.source);
+                    amc.media_user_token = imported.token;
+                },
+                None => return Err("No Media User Token found in Cider / Apple Music Electron: log in there first, or paste the token into the Media User Token option".into()),
+            }
+        }
+        if amc.media_user_token.trim().is_empty() {
+            return Err("Media User Token is empty: log in to music.apple.com, copy the `media-user-token` cookie and paste it into the Media User Token option, or enable importing it from Cider".into());
+        }
+        if !amc.genre_mapping.trim().is_empty() {
+            amc.genre_map = serde_json::from_str(&amc.genre_mapping).map_err(|e| format!("Invalid genre mapping JSON: {e}"))?;
+        }
+        amc.filename_regex = match amc.filename_template.trim() {
+            "" => None,
+            template => Some(filename_regex(template).map_err(|e| format!("Invalid filename_template `{template}`: {e}"))?),
+        };
+        let invalid = |s: &&str| !s.is_empty() && !(s.len() == 2 && s.chars().all(|c| c.is_ascii_alphabetic()));
+        if let Some(storefront) = Some(amc.storefront.trim()).filter(invalid) {
+            return Err(format!("Invalid storefront `{storefront}`: use a two letter country code (e.g. us)").into());
+        }
+        if let Some(storefront) = amc.fallback_storefronts.split(',').map(|s| s.trim()).find(invalid) {
+            return Err(format!("Invalid fallback_storefronts entry `{storefront}`: use two letter country codes separated by comma (e.g. us,gb,jp)").into());
+        }
+        // Same token and storefront, apply new options and keep session
+        if let Some(am) = self.apple_music.as_mut() {
+            if am.config.media_user_token == amc.media_user_token && am.config.storefront == amc.storefront {
+                am.reconfigure(amc, config);
+                am.diagnostics.lock().unwrap().clear();
+                am.metadata_rows.lock().unwrap().clear();
+                *am.playlist.lock().unwrap() = None;
+                // Files could have been retagged since
+                am.prefetched.lock().unwrap().clear();
+                return Ok(Box::new(am.clone()));
+            }
+        }
+        // Create new
+        let mut am = AppleMusic::new(&amc.media_user_token)?;
+        if !amc.storefront.trim().is_empty() {
+            am = am.with_storefront(&amc.storefront.trim().to_lowercase());
+        }
+        am.reconfigure(amc, config);
+        // Chcek token, storefronts and search before the run
+        let fallback: Vec<String> = am.config.fallback_storefronts.split(',').map(|s| s.trim().to_lowercase()).filter(|s| !s.is_empty()).collect();
+        let report = am.health_check(&fallback.iter().map(|s| s.as_str()).collect::<Vec<_>>());
+        if !report.ok() {
+            return Err(format!("Apple Music health check failed:\n{report}").into());
+        }
+        info!("Apple Music health check:\n{report}");
+        self.apple_music = Some(am.clone());
+        Ok(Box::new(am))
+    }
+
+    fn info(&self) -> PlatformInfo {
+        let mut supported_tags = supported_tags!(Title, Artist, AlbumArtist, Album, Genre, Style, Mood, AlbumArt, URL, Label, CatalogNumber, TrackId, ReleaseId, Duration,
+            TrackNumber, TrackTotal, DiscNumber, ISRC, ReleaseDate, SyncedLyrics, UnsyncedLyrics, Composer, Barcode, OtherTags);
+        if !self.apple_music.as_ref().map(|am| am.config.id_as_catalog_number).unwrap_or(true) {
+            supported_tags.retain(|t| *t != SupportedTag::CatalogNumber);
+        }
+
+        let info = PlatformInfo {
+            id: "apple_music".to_string(),
+            name: "Apple Music".to_string(),
+            description: "Incl. album art up to 3000px, lyrics and more. Requires token".to_string(),
+            version: "1.0.0".to_string(),
+            icon: include_bytes!("icon.png"),
+            max_threads: self.apple_music.as_ref().map(|am| am.config.threads.clamp(1, MAX_THREADS)).unwrap_or(DEFAULT_THREADS) as u16,
+            requires_auth: true,
+            supported_tags,
+            custom_options: PlatformCustomOptions::new()
+                .add("media_user_token", "Media User Token", PlatformCustomOptionValue::String { value: String::new(), hidden: Some(true) })
+                .add("id_as_catalog_number", "Write Apple song ID as catalog number", PlatformCustomOptionValue::Boolean { value: true })
+                .add("fetch_album", "Fetch full album (extra request per album)", PlatformCustomOptionValue::Boolean { value: false })
+                .add("genres", "Genres", PlatformCustomOptionValue::Option {
+                    values: vec!["all".to_string(), "without_music".to_string(), "primary".to_string()], value: "all".to_string()
+                })
+                .add("genres_language", "Genres language (e.g. es_ES, empty = default)", PlatformCustomOptionValue::String { value: String::new(), hidden: None })
+                .add("genre_mapping", "Genre mapping JSON ({\"Hip-Hop/Rap\": \"Hip Hop\"})", PlatformCustomOptionValue::String { value: String::new(), hidden: None })
+                .add("editorial_notes", "Write album editorial notes to (requires album fetch)", PlatformCustomOptionValue::Option {
+                    values: vec!["disabled".to_string(), "comment".to_string(), "album_description".to_string()], value: "disabled".to_string()
+                })
+                .add("editorial_tags", "Write editorial moods / activities to", PlatformCustomOptionValue::Option {
+                    values: vec!["disabled".to_string(), "style".to_string(), "mood".to_string()], value: "disabled".to_string()
+                })
+                .add("prefer_digital_masters", "Prefer Apple Digital Masters", PlatformCustomOptionValue::Boolean { value: false })
+                .add("prefer_non_compilations", "Prefer non-compilation releases", PlatformCustomOptionValue::Boolean { value: false })
+                .add("original_release_date", "Use album date as release date and earliest date as original date", PlatformCustomOptionValue::Boolean { value: false })
+                .add("download_preview", "Download 30s preview next to the file", PlatformCustomOptionValue::Boolean { value: false })
+                .add("all_artist_urls", "Write URLs of all artists to APPLE_ARTIST_URL", PlatformCustomOptionValue::Boolean { value: false })
+                .add("artwork_colors", "Write artwork colors (APPLE_ART_BG_COLOR, APPLE_ART_TEXT_COLOR)", PlatformCustomOptionValue::Boolean { value: false })
+                .add("catalog_ids", "Write Apple album and artist IDs (APPLE_ALBUM_ID, APPLE_ARTIST_ID)", PlatformCustomOptionValue::Boolean { value: false })
+                .add("artist_info", "Write artist bio, origin and born/formed date (extra request per artist)", PlatformCustomOptionValue::Boolean { value: false })
+                .add("classical_mode", "Classical mode (composer attribution as album artist / composer)", PlatformCustomOptionValue::Boolean { value: false })
+                .add("comment_template", "Comment template ({storefront}, {date}, {id}, {album_id}, {isrc}, {url})", PlatformCustomOptionValue::String { value: String::new(), hidden: None })
+                .add("credits", "Fetch credits (producers, engineers, performers)", PlatformCustomOptionValue::Boolean { value: false })
+                .add("single_artist", "Write artist as single string (\"A & B\")", PlatformCustomOptionValue::Boolean { value: false })
+                .add("explicit_lyrics", "Use explicit version lyrics for clean matches", PlatformCustomOptionValue::Boolean { value: false })
+                .add("karaoke_json", "Write karaoke JSON lyrics file", PlatformCustomOptionValue::Boolean { value: false })
+                .add("lyrics_wrap", "Wrap unsynced lyrics lines to length (0 = disabled)", PlatformCustomOptionValue::Number { min: 0, max: 200, step: 1, value: 0 })
+                .add("max_duration_difference", "Discard candidates with duration difference over (seconds, 0 = disabled)", PlatformCustomOptionValue::Number { min: 0, max: 600, step: 1, value: 0 })
+                .add("album_weight", "Album tag similarity weight in matching (%, 0 = disabled)", PlatformCustomOptionValue::Number { min: 0, max: 100, step: 1, value: 0 })
+                .add("prefer_original_albums", "Prefer original albums over singles, greatest hits and compilations", PlatformCustomOptionValue::Boolean { value: false })
+                .add("explicit_preference", "Explicit / clean version preference", PlatformCustomOptionValue::Option {
+                    values: vec!["any".to_string(), "prefer_explicit".to_string(), "prefer_clean".to_string()], value: "any".to_string()
+                })
+                .add("blocklist", "Ignore results with artist / title / album containing (comma separated)", PlatformCustomOptionValue::String { value: "karaoke,originally performed,made famous,in the style of,tribute".to_string(), hidden: None })
+                .add("live_versions", "Live versions not matching the file (live / studio)", PlatformCustomOptionValue::Option {
+                    values: vec!["disabled".to_string(), "penalize".to_string(), "exclude".to_string()], value: "penalize".to_string()
+                })
+                .add("version_matching", "Remix / edit versions not matching the file title", PlatformCustomOptionValue::Option {
+                    values: vec!["disabled".to_string(), "penalize".to_string(), "exclude".to_string()], value: "disabled".to_string()
+                })
+                .add("year_weight", "Release year proximity weight in matching (%, 0 = disabled)", PlatformCustomOptionValue::Number { min: 0, max: 100, step: 1, value: 0 })
+                .add("verify_featured", "Verify featured / additional artists against results", PlatformCustomOptionValue::Boolean { value: true })
+                .add("album_context", "Prefer album matched for previous tracks in the same folder", PlatformCustomOptionValue::Boolean { value: false })
+                .add("match_report", "Write match score breakdown to APPLE_MATCH_REPORT (debugging)", PlatformCustomOptionValue::Boolean { value: false })
+                .add("lyrics_highlights", "Compare search lyric snippets with embedded lyrics", PlatformCustomOptionValue::Boolean { value: false })
+                .add("strict_matching", "Strict matching (only accept matches confirmed by ISRC or duration within 2s)", PlatformCustomOptionValue::Boolean { value: false })
+                .add("fallback_storefronts", "Fallback storefronts to search if no match (comma separated, e.g. us,gb,jp)", PlatformCustomOptionValue::String { value: String::new(), hidden: None })
+                .add("filename_template", "Filename template for files without artist / title (%artist%, %title%)", PlatformCustomOptionValue::String { value: "%artist% - %title%".to_string(), hidden: None })
+                .add("audio_locale", "Preferred audio language on equal match (e.g. en, ja, empty = file language tag)", PlatformCustomOptionValue::String { value: String::new(), hidden: None })
+                .add("verify_artwork", "Verify match by comparing embedded artwork (extra request per track)", PlatformCustomOptionValue::Boolean { value: false })
+                .add("artist_aliases", "Resolve renamed / stylized artists by artist search (extra request)", PlatformCustomOptionValue::Boolean { value: false })
+                .add("penalized_patterns", "Penalize titles containing, unless file title does (comma separated)", PlatformCustomOptionValue::String { value: "sped up,slowed,nightcore,reverb,8d audio".to_string(), hidden: None })
+                .add("duration_weight", "Duration difference weight in matching (%, 0 = disabled)", PlatformCustomOptionValue::Number { min: 0, max: 100, step: 1, value: 5 })
+                .add("strictness", "Match strictness override for Apple Music (%, 0 = global)", PlatformCustomOptionValue::Number { min: 0, max: 100, step: 1, value: 0 })
+                .add("verify_isrc", "Reject matches with ISRC different from file's ISRC", PlatformCustomOptionValue::Boolean { value: false })
+                .add("multi_query", "Also search by title + album and title only (extra requests)", PlatformCustomOptionValue::Boolean { value: false })
+                .add("skip_tagged", "Skip files already tagged with Apple Music track ID", PlatformCustomOptionValue::Boolean { value: false })
+                .add("skip_tagged_days", "Only skip files tagged within days (0 = any)", PlatformCustomOptionValue::Number { min: 0, max: 3650, step: 1, value: 0 })
+                .add("folder_album", "Prefer album matching folder name for files without album tag", PlatformCustomOptionValue::Boolean { value: false })
+                .add("max_artwork_size", "Max artwork size", PlatformCustomOptionValue::Option {
+                    values: vec!["500".to_string(), "1000".to_string(), "1400".to_string(), "3000".to_string(), "original".to_string()], value: "original".to_string()
+                })
+                .add("artwork_format", "Artwork format", PlatformCustomOptionValue::Option {
+                    values: vec!["jpg".to_string(), "png".to_string(), "webp".to_string()], value: "jpg".to_string()
+                })
+                .add("animated_artwork", "Download animated artwork next to the file (requires album fetch)", PlatformCustomOptionValue::Boolean { value: false })
+                .add("artist_image", "Artist image (extra request per artist)", PlatformCustomOptionValue::Option {
+                    values: vec!["disabled".to_string(), "tag".to_string(), "download".to_string()], value: "disabled".to_string()
+                })
+                .add("artwork_crop", "Artwork crop (square, bb = uncropped, cc = center crop, sr = source ratio)", PlatformCustomOptionValue::Option {
+                    values: vec!["square".to_string(), "bb".to_string(), "cc".to_string(), "sr".to_string()], value: "square".to_string()
+                })
+                .add("artwork_urls", "Write small / medium / original artwork URLs (APPLE_ARTWORK_*)", PlatformCustomOptionValue::Boolean { value: false })
+                .add("album_artwork", "Use album edition artwork instead of song artwork (requires album fetch)", PlatformCustomOptionValue::Boolean { value: false })
+                .add("validate_artwork", "Check artwork exists and fall back to smaller sizes (extra request)", PlatformCustomOptionValue::Boolean { value: false })
+                .add("min_artwork_size", "Minimum artwork resolution, smaller is not written (px, 0 = any)", PlatformCustomOptionValue::Number { min: 0, max: 3000, step: 100, value: 0 })
+                .add("cover_file", "Save artwork to album folder as (e.g. cover.jpg, empty = disabled)", PlatformCustomOptionValue::String { value: String::new(), hidden: None })
+                .add("album_images", "Download additional album images into album folder (requires album fetch)", PlatformCustomOptionValue::Boolean { value: false })
+                .add("album_mode", "Album mode: match remaining tracks in folder against the tracklist of the first match", PlatformCustomOptionValue::Boolean { value: false })
+                .add("language", "Metadata language (e.g. en_GB, de_DE, ja_JP)", PlatformCustomOptionValue::String { value: "en_GB".to_string(), hidden: None })
+                .add("threads", "Concurrent tracks for Apple Music (1 - 16)", PlatformCustomOptionValue::Number { min: 1, max: 16, step: 1, value: 4 })
+                .add("raw_attributes", "Copy raw attributes into custom tags (comma separated paths, e.g. attributes.composerName or path=TAG)", PlatformCustomOptionValue::String { value: String::new(), hidden: None })
+                .add("storefront", "Storefront to tag from (e.g. jp, de, empty = account storefront)", PlatformCustomOptionValue::String { value: String::new(), hidden: None })
+                .add("lyrics_language", "Lyrics language (e.g. ja_JP, empty = metadata language)", PlatformCustomOptionValue::String { value: String::new(), hidden: None })
+                .add("profiles", "Profiles JSON, options by profile name (e.g. {\"japan\": {\"storefront\": \"jp\", \"language\": \"ja_JP\"}})", PlatformCustomOptionValue::String { value: String::new(), hidden: None })
+                .add("profile", "Profile to use for this run (empty = none)", PlatformCustomOptionValue::String { value: String::new(), hidden: None })
+                .add("diagnostics_file", "Write per track diagnostics JSON report to file (empty = disabled)", PlatformCustomOptionValue::String { value: String::new(), hidden: None })
+                .add("library_search", "Search in your library (including uploads) before catalog", PlatformCustomOptionValue::Boolean { value: false })
+                .add("add_to_library", "Add matched songs to your Apple Music library", PlatformCustomOptionValue::Boolean { value: false })
+                .add("playlist", "Add matched songs to playlist, name or library playlist ID (e.g. OneTagger matched {date}, empty = disabled)", PlatformCustomOptionValue::String { value: String::new(), hidden: None })
+                .add("source_playlist", "Match against tracks of playlist first, URL or ID (empty = disabled)", PlatformCustomOptionValue::String { value: String::new(), hidden: None })
+                .add("library_ratings", "Write loved / disliked from Apple Music (100 / 20), written as RATING text tag, not POPM (most players only read POPM for MP3)", PlatformCustomOptionValue::Boolean { value: false })
+                .add("play_counts", "Write play count / last played of library songs (APPLE_PLAY_COUNT, APPLE_LAST_PLAYED)", PlatformCustomOptionValue::Boolean { value: false })
+                .add("metadata_sidecar", "Export all fetched metadata (json = <file>.apple.json, csv = metadata_csv_file)", PlatformCustomOptionValue::Option { values: vec!["disabled".to_string(), "json".to_string(), "csv".to_string()], value: "disabled".to_string() })
+                .add("metadata_csv_file", "Metadata CSV file of the run (for csv sidecar)", PlatformCustomOptionValue::String { value: String::new(), hidden: None })
+                .add("song_link", "Write song.link URL into SONGLINK_URL (Odesli, max 10 tracks per minute)", PlatformCustomOptionValue::Boolean { value: false })
+                .add("platform_ids", "Write Deezer / Spotify track IDs by ISRC (DEEZER_TRACK_ID, SPOTIFY_TRACK_ID)", PlatformCustomOptionValue::Boolean { value: false })
+                .add("spotify_client_id", "Spotify app client ID (for Spotify IDs)", PlatformCustomOptionValue::String { value: String::new(), hidden: None })
+                .add("spotify_client_secret", "Spotify app client secret", PlatformCustomOptionValue::String { value: String::new(), hidden: Some(true) })
+                .add("album_nfo", "Write Kodi / Jellyfin album.nfo into album folder (requires album fetch)", PlatformCustomOptionValue::Boolean { value: false })
+                .add("post_match_command", "Command run after each match, gets the track JSON on stdin and file path in ONETAGGER_PATH (empty = disabled)", PlatformCustomOptionValue::String { value: String::new(), hidden: None })
+                .add("import_token", "Import Media User Token from Cider / Apple Music Electron if empty", PlatformCustomOptionValue::Boolean { value: false })
+                .add("album_report", "Write album report album.json (tracklist, ISRCs, UPC, label, artwork URLs) into album folder (requires album fetch)", PlatformCustomOptionValue::Boolean { value: false })
+                .add("enrich_threads", "Concurrent tracks fetching album / lyrics / extras after matching (1 - 16)", PlatformCustomOptionValue::Number { min: 1, max: 16, step: 1, value: 4 }),
+        };
+        #[cfg(feature = "musicbrainz")]
+        let info = PlatformInfo {
+            custom_options: info.custom_options
+                .add("musicbrainz_ids", "Write MusicBrainz recording / release IDs looked up by ISRC (1 request per second)", PlatformCustomOptionValue::Boolean { value: false }),
+            ..info
+        };
+        info
+    }
+}
+
+onetagger_tagger::create_plugin!(AppleMusicBuilder, AppleMusic);
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub(crate) struct AppleMusicConfig {
+    pub media_user_token: String,
+    /// Write song ID as catalog number
+    pub id_as_catalog_number: bool,
+    /// Fetch the full album resource for matched tracks
+    pub fetch_album: bool,
+    /// Which genres to keep
+    pub genres: GenresMode,
+    /// Language to fetch genres in, empty = metadata language
+    pub genres_language: String,
+    /// JSON object mapping Apple genres to user genres
+    pub genre_mapping: String,
+    /// Parsed `genre_mapping`
+    #[serde(skip)]
+    pub genre_map: HashMap<String, String>,
+    /// Where to write album editorial notes
+    pub editorial_notes: EditorialNotesTag,
+    /// Where to write editorial moods / activities
+    pub editorial_tags: EditorialTagsTarget,
+    /// Prefer Apple Digital Masters on equal match
+    pub prefer_digital_masters: bool,
+    /// Prefer non compilation albums on equal match
+    pub prefer_non_compilations: bool,
+    /// Album release date as release date, earliest of song and album as original date
+    pub original_release_date: bool,
+    /// Download preview clip next to the audio file
+    pub download_preview: bool,
+    /// Write URL of every artist, not just the primary
+    pub all_artist_urls: bool,
+    /// Write artwork background and text colors
+    pub artwork_colors: bool,
+    /// Write album and artist catalog IDs
+    pub catalog_ids: bool,
+    /// Fetch primary artist bio, origin and born/formed date
+    pub artist_info: bool,
+    /// Use classical attribution as composer and album artist
+    pub classical_mode: bool,
+    /// Comment template, empty = disabled
+    pub comment_template: String,
+    /// Fetch song credits
+    pub credits: bool,
+    /// Use combined `artistName` instead of artists relationship
+    pub single_artist: bool,
+    /// Fetch lyrics from the explicit equivalent of clean matches
+    pub explicit_lyrics: bool,
+    /// Write `.karaoke.json` sidecar with per-word timing
+    pub karaoke_json: bool,
+    /// Max unsynced lyrics line length, 0 = disabled
+    pub lyrics_wrap: u32,
+    /// Max candidate duration difference in seconds, 0 = disabled
+    pub max_duration_difference: u32,
+    /// Weight of album similarity when ranking candidates in %, 0 = disabled
+    pub album_weight: u32,
+    /// Prefer original albums over singles, greatest hits and compilations on equal match
+    pub prefer_original_albums: bool,
+    /// Explicit / clean edition preference
+    pub explicit_preference: ExplicitPreference,
+    /// Comma separated artist / title / album patterns of results to ignore
+    pub blocklist: String,
+    /// Live / studio mismatch handling
+    pub live_versions: MismatchMode,
+    /// Remix / edit version mismatch handling
+    pub version_matching: MismatchMode,
+    /// Weight of release year proximity when ranking candidates in %, 0 = disabled
+    pub year_weight: u32,
+    /// Penalize candidates with missing / extra featured or additional artists
+    pub verify_featured: bool,
+    /// Prefer album matched for other tracks in the same folder with the same album tag
+    pub album_context: bool,
+    /// Write match score breakdown tag
+    pub match_report: bool,
+    /// Score candidates by search lyric highlights found in embedded lyrics
+    pub lyrics_highlights: bool,
+    /// Only accept matches confirmed by ISRC or duration
+    pub strict_matching: bool,
+    /// Comma separated storefronts to retry search in if there is no match
+    pub fallback_storefronts: String,
+    /// Template to parse artist / title from filename of untagged files
+    pub filename_template: String,
+    /// Compiled `filename_template`
+    #[serde(skip)]
+    pub filename_regex: Option<regex::Regex>,
+    /// Preferred audio locale on equal match, empty = file language tag
+    pub audio_locale: String,
+    /// Lower accuracy of matches with artwork different from embedded
+    pub verify_artwork: bool,
+    /// Accept artist mismatches if the candidate artist is in file artist search results
+    pub artist_aliases: bool,
+    /// Comma separated title patterns to penalize unless in file title
+    pub penalized_patterns: String,
+    /// Weight of duration difference when ranking candidates in %, 0 = disabled
+    pub duration_weight: u32,
+    /// Strictness override in %, 0 = global strictness
+    pub strictness: u32,
+    /// Reject matches with ISRC different from the file
+    pub verify_isrc: bool,
+    /// Pool results of title + album and title only searches
+    pub multi_query: bool,
+    /// Skip files with Apple track ID from previous run
+    pub skip_tagged: bool,
+    /// Only skip files tagged within days, 0 = any
+    pub skip_tagged_days: u32,
+    /// Prefer album matching the folder name when album tag is missing
+    pub folder_album: bool,
+    /// Max artwork size in px, `original` = native size
+    pub max_artwork_size: String,
+    /// Format of the artwork
+    pub artwork_format: ArtworkFormat,
+    /// Download motion artwork as `.cover.mp4` sidecar
+    pub animated_artwork: bool,
+    /// Write artist image URL / download `artist.jpg`
+    pub artist_image: ArtistImage,
+    /// Artwork crop variant
+    pub artwork_crop: ArtworkCrop,
+    /// Write artwork URLs in multiple sizes
+    pub artwork_urls: bool,
+    /// Use full album artwork instead of song artwork
+    pub album_artwork: bool,
+    /// Validate artwork URL and step down to smaller sizes
+    pub validate_artwork: bool,
+    /// Minimum artwork resolution, smaller artwork is not written
+    pub min_artwork_size: u32,
+    /// Filename of artwork saved into the track folder, empty = disabled
+    pub cover_file: String,
+    /// Download editorial album images into the album folder
+    pub album_images: bool,
+    /// Match other tracks in folder against the tracklist of the first matched album
+    pub album_mode: bool,
+    /// Language of titles, genres and lyrics metadata, e.g. `en_GB`
+    pub language: String,
+    /// Max tracks matched concurrently by this source
+    pub threads: usize,
+    /// Raw attribute paths copied into custom tags, `path` or `path=TAG`, comma separated
+    pub raw_attributes: String,
+    /// Storefront to search in, empty = account storefront
+    pub storefront: String,
+    /// Language of lyrics, empty = `language`
+    pub lyrics_language: String,
+    /// Named profiles, JSON object of option overrides by name
+    pub profiles: String,
+    /// Name of profile from `profiles` applied over the options
+    pub profile: String,
+    /// Path of run diagnostics JSON report, empty = disabled
+    pub diagnostics_file: String,
+    /// Match against user library first, catalog is the fallback
+    pub library_search: bool,
+    /// Add confidently matched catalog songs to user library
+    pub add_to_library: bool,
+    /// Library playlist name (`{date}` = today) or ID matched songs are added to, empty = disabled
+    pub playlist: String,
+    /// Catalog or library playlist URL / ID whose tracks are matched first, empty = disabled
+    pub source_playlist: String,
+    /// Write loved / disliked status into RATING text tag (TXXX / Vorbis / `rate`), not ID3 POPM
+    pub library_ratings: bool,
+    /// Write library play count / last played date into custom tags
+    pub play_counts: bool,
+    /// Export all fetched metadata as sidecar
+    pub metadata_sidecar: MetadataSidecar,
+    /// Path of the run metadata CSV
+    pub metadata_csv_file: String,
+    /// Write MusicBrainz IDs looked up by ISRC
+    #[cfg(feature = "musicbrainz")]
+    pub musicbrainz_ids: bool,
+    /// Write song.link URL resolved by Odesli
+    pub song_link: bool,
+    /// Write Deezer / Spotify track IDs looked up by ISRC
+    pub platform_ids: bool,
+    /// Spotify app client ID, Spotify lookup is skipped without it
+    pub spotify_client_id: String,
+    /// Spotify app client secret
+    pub spotify_client_secret: String,
+    /// Write album.nfo for media centers into album folder
+    pub album_nfo: bool,
+    /// Shell command run after each match with the Track JSON on stdin, empty = disabled
+    pub post_match_command: String,
+    /// Import token from installed desktop clients when the option is empty
+    pub import_token: bool,
+    /// Write album report JSON into album folder
+    pub album_report: bool,
+    /// Concurrency of the enrichment stage
+    pub enrich_threads: usize,
+}
+
+impl Default for AppleMusicConfig {
+    fn default() -> Self {
+        AppleMusicConfig {
+            media_user_token: String::new(),
+            id_as_catalog_number: true,
+            fetch_album: false,
+            genres: GenresMode::All,
+            genres_language: String::new(),
+            genre_mapping: String::new(),
+            genre_map: HashMap::new(),
+            editorial_notes: EditorialNotesTag::Disabled,
+            editorial_tags: EditorialTagsTarget::Disabled,
+            prefer_digital_masters: false,
+            prefer_non_compilations: false,
+            original_release_date: false,
+            download_preview: false,
+            all_artist_urls: false,
+            artwork_colors: false,
+            catalog_ids: false,
+            artist_info: false,
+            classical_mode: false,
+            comment_template: String::new(),
+            credits: false,
+            single_artist: false,
+            explicit_lyrics: false,
+            karaoke_json: false,
+            lyrics_wrap: 0,
+            max_duration_difference: 0,
+            album_weight: 0,
+            prefer_original_albums: false,
+            explicit_preference: ExplicitPreference::Any,
+            blocklist: "karaoke,originally performed,made famous,in the style of,tribute".to_string(),
+            live_versions: MismatchMode::Penalize,
+            version_matching: MismatchMode::Disabled,
+            year_weight: 0,
+            verify_featured: true,
+            album_context: false,
+            match_report: false,
+            lyrics_highlights: false,
+            strict_matching: false,
+            fallback_storefronts: String::new(),
+            filename_template: "%artist% - %title%".to_string(),
+            filename_regex: filename_regex("%artist% - %title%").ok(),
+            audio_locale: String::new(),
+            verify_artwork: false,
+            artist_aliases: false,
+            penalized_patterns: "sped up,slowed,nightcore,reverb,8d audio".to_string(),
+            duration_weight: 5,
+            strictness: 0,
+            verify_isrc: false,
+            multi_query: false,
+            skip_tagged: false,
+            skip_tagged_days: 0,
+            folder_album: false,
+            max_artwork_size: "original".to_string(),
+            artwork_format: ArtworkFormat::Jpg,
+            animated_artwork: false,
+            artist_image: ArtistImage::Disabled,
+            artwork_crop: ArtworkCrop::Square,
+            artwork_urls: false,
+            album_artwork: false,
+            validate_artwork: false,
+            min_artwork_size: 0,
+            cover_file: String::new(),
+            album_images: false,
+            album_mode: false,
+            language: "en_GB".to_string(),
+            threads: DEFAULT_THREADS,
+            raw_attributes: String::new(),
+            storefront: String::new(),
+            lyrics_language: String::new(),
+            profiles: String::new(),
+            profile: String::new(),
+            diagnostics_file: String::new(),
+            library_search: false,
+            add_to_library: false,
+            playlist: String::new(),
+            source_playlist: String::new(),
+            library_ratings: false,
+            play_counts: false,
+            metadata_sidecar: MetadataSidecar::Disabled,
+            metadata_csv_file: String::new(),
+            #[cfg(feature = "musicbrainz")]
+            musicbrainz_ids: false,
+            song_link: false,
+            platform_ids: false,
+            spotify_client_id: String::new(),
+            spotify_client_secret: String::new(),
+            album_nfo: false,
+            post_match_command: String::new(),
+            import_token: false,
+            album_report: false,
+            enrich_threads: 4,
+        }
+    }
+}
+
+/// Format of the metadata sidecar
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum MetadataSidecar {
+    Disabled,
+    /// `<file>.apple.json` per file
+    Json,
+    /// Row per file in `metadata_csv_file`
+    Csv,
+}
+
+/// What to do with artist image
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ArtistImage {
+    Disabled,
+    /// Write URL to APPLE_ARTIST_IMAGE
+    Tag,
+    /// Tag and download `artist.jpg` into artist folder
+    Download,
+}
+
+/// How to handle candidates not matching the file (live / studio, versions)
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum MismatchMode {
+    Disabled,
+    Penalize,
+    Exclude,
+}
+
+/// Which edition to prefer when both explicit and clean versions exist
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ExplicitPreference {
+    Any,
+    PreferExplicit,
+    PreferClean,
+}
+
+impl ExplicitPreference {
+    /// Wanted `SongAttributes::explicit` value
+    pub fn explicit(&self) -> Option<bool> {
+        match self {
+            ExplicitPreference::Any => None,
+            ExplicitPreference::PreferExplicit => Some(true),
+            ExplicitPreference::PreferClean => Some(false),
+        }
+    }
+}
+
+/// Which of Apple's genres to keep
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum GenresMode {
+    All,
+    /// Drop the generic "Music" genre
+    WithoutMusic,
+    /// First genre other than "Music"
+    Primary
+}
+
+impl GenresMode {
+    /// Filter genres of song
+    fn apply(&self, song: &ItemMeta<SongAttributes>) -> Vec<String> {
+        self.apply_localized(song, song)
+    }
+
+    /// Filter genres of song fetched in other language. "Music" is detected by ID from the genre relationship,
+    /// without it by the English genres of same order
+    fn apply_localized(&self, song: &ItemMeta<SongAttributes>, localized: &ItemMeta<SongAttributes>) -> Vec<String> {
+        if *self == GenresMode::All {
+            return localized.attributes.genre_names.clone();
+        }
+        let relationship = localized.relationships.as_ref().map(|r| r.genres.as_ref()).flatten().filter(|g| !g.data.is_empty());
+        let mut genres: Vec<String> = match relationship {
+            Some(genres) => genres.data.iter().filter(|g| g.id != MUSIC_GENRE_ID).map(|g| g.attributes.name.to_string()).collect(),
+            None => localized.attributes.genre_names.iter().enumerate()
+                .filter(|(i, _)| song.attributes.genre_names.get(*i).map(|g| g != "Music").unwrap_or(true))
+                .map(|(_, g)| g.to_string())
+                .collect()
+        };
+        if *self == GenresMode::Primary {
+            genres.truncate(1);
+        }
+        genres
+    }
+}
+
+/// Target tag of editorial moods and activities
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum EditorialTagsTarget {
+    Disabled,
+    Style,
+    Mood
+}
+
+/// Target tag of album editorial notes
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum EditorialNotesTag {
+    Disabled,
+    Comment,
+    AlbumDescription
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn html_to_text_breaks_and_tags() {
+        assert_eq!(html_to_text("<p>First<br/>line</p><p><b>Second</b> line</p>"), "First\nline\nSecond line");
+        assert_eq!(html_to_text("One<BR >Two"), "One\nTwo");
+    }
+
+    #[test]
+    fn html_to_text_entities() {
+        assert_eq!(html_to_text("Rock &amp; Roll &quot;Live&quot;"), "Rock & Roll \"Live\"");
+        // Double encoded entity is decoded once
+        assert_eq!(html_to_text("&amp;lt;3"), "&lt;3");
+    }
+
+    #[test]
+    fn resize_artwork_url_sizes() {
+        let url = "https://is1-ssl.mzstatic.com/image/thumb/Music115/v4/ab/cover.jpg";
+        assert_eq!(resize_artwork_url(&format!("{url}/3000x3000bb.jpg"), 1400), Some(format!("{url}/1400x1400bb.jpg")));
+        assert_eq!(resize_artwork_url(&format!("{url}/600x600bb.jpg"), 1400), None);
+        assert_eq!(resize_artwork_url(&format!("{url}/3000x2000bb.png"), 1500), Some(format!("{url}/1500x1000bb.png")));
+    }
+
+    #[test]
+    fn parse_release_date_precision() {
+        assert_eq!(parse_release_date("1997"), (None, Some(1997)));
+        assert_eq!(parse_release_date("1997-05"), (None, Some(1997)));
+        assert_eq!(parse_release_date("1997-13"), (None, None));
+        assert_eq!(parse_release_date("1997-05-21"), (NaiveDate::from_ymd_opt(1997, 5, 21), Some(1997)));
+        assert_eq!(parse_release_date("soon"), (None, None));
+        assert_eq!(parse_release_date(""), (None, None));
+    }
+
+    fn line(text: &str, start: Option<Duration>) -> LyricsLine {
+        LyricsLine { text: text.to_string(), start, end: None, parts: vec![] }
+    }
+
+    fn texts(lyrics: &Lyrics) -> Vec<&str> {
+        lyrics.paragraphs.iter().flatten().map(|l| l.text.as_str()).collect()
+    }
+
+    #[test]
+    fn wrap_lyrics_width() {
+        let lyrics = Lyrics {
+            paragraphs: vec![
+                vec![line("short line", None), line("one two three four five", None)],
+                vec![line("synced line longer than width", Some(Duration::from_secs(1)))],
+            ],
+            language: "en".to_string(),
+        };
+        let wrapped = AppleMusic::wrap_lyrics(lyrics, 10);
+        assert_eq!(texts(&wrapped), vec!["short line", "one two", "three four", "five", "synced line longer than width"]);
+        assert_eq!(wrapped.paragraphs[1][0].start, Some(Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn wrap_lyrics_long_word() {
+        let lyrics = Lyrics { paragraphs: vec![vec![line("a supercalifragilistic b", None)]], language: "en".to_string() };
+        assert_eq!(texts(&AppleMusic::wrap_lyrics(lyrics, 8)), vec!["a", "supercalifragilistic", "b"]);
+    }
+
+    #[test]
+    fn split_composers_separators() {
+        assert_eq!(split_composers("John Lennon, Paul McCartney & George Harrison; Ringo Starr"),
+            vec!["John Lennon", "Paul McCartney", "George Harrison", "Ringo Starr"]);
+        assert_eq!(split_composers("AC/DC"), vec!["AC/DC"]);
+        assert_eq!(split_composers("Angus Young, Malcolm Young,"), vec!["Angus Young", "Malcolm Young"]);
+        assert_eq!(split_composers("R&B Writers"), vec!["R&B Writers"]);
+    }
+
+    #[test]
+    fn flatten_json_keys() {
+        let value = serde_json::json!({
+            "name": "Song",
+            "rating": 5,
+            "explicit": false,
+            "artwork": { "width": 3000, "url": null },
+            "genres": ["Pop", { "id": "14" }]
+        });
+        let mut out = BTreeMap::new();
+        flatten_json("", &value, &mut out);
+        let expected: BTreeMap<String, String> = [
+            ("name", "Song"),
+            ("rating", "5"),
+            ("explicit", "false"),
+            ("artwork.width", "3000"),
+            ("genres.0", "Pop"),
+            ("genres.1.id", "14"),
+        ].into_iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        assert_eq!(out, expected);
+
+        let mut out = BTreeMap::new();
+        flatten_json("song", &serde_json::json!({ "id": "1" }), &mut out);
+        assert_eq!(out.get("song.id").map(String::as_str), Some("1"));
+    }
+
+    const PROFILES: &str = r#"{"japanese": {"language": "ja", "lyrics": true, "media_user_token": "other", "profile": "loop"}}"#;
+
+    #[test]
+    fn apply_profile_empty() {
+        let custom = serde_json::json!({ "profile": " ", "profiles": PROFILES, "language": "en_GB" });
+        assert_eq!(apply_profile(custom.clone()).unwrap(), custom);
+    }
+
+    #[test]
+    fn apply_profile_unknown() {
+        let custom = serde_json::json!({ "profile": "korean", "profiles": PROFILES });
+        let error = apply_profile(custom).unwrap_err().to_string();
+        assert_eq!(error, "Profile `korean` is not defined in profiles");
+    }
+
+    #[test]
+    fn apply_profile_overrides() {
+        let custom = serde_json::json!({
+            "profile": "japanese",
+            "profiles": PROFILES,
+            "language": "en_GB",
+            "lyrics": false,
+            "media_user_token": "token",
+            "genres": "primary"
+        });
+        let applied = apply_profile(custom).unwrap();
+        assert_eq!(applied["language"].as_str(), Some("ja"));
+        assert_eq!(applied["lyrics"].as_bool(), Some(true));
+        // Token and profile selection can't be overridden, other options are kept
+        assert_eq!(applied["media_user_token"].as_str(), Some("token"));
+        assert_eq!(applied["profile"].as_str(), Some("japanese"));
+        assert_eq!(applied["genres"].as_str(), Some("primary"));
+    }
+
+    /// First song of the recorded search response
+    fn fixture_song() -> ItemMeta<SongAttributes> {
+        let response: SearchResultsResponse = serde_json::from_str(include_str!("../tests/fixtures/search.json")).unwrap();
+        response.results.song.data.into_iter().next().unwrap()
+    }
+
+    #[test]
+    fn raw_attributes_paths() {
+        let paths = "attributes.isrc, relationships.albums.data.0.attributes.upc=UPC, attributes.genreNames, attributes.discNumber, attributes.missing";
+        assert_eq!(raw_attributes(&fixture_song(), paths), vec![
+            (FrameName::same("APPLE_ISRC"), vec!["USQX91300108".to_string()]),
+            (FrameName::same("UPC"), vec!["886443919266".to_string()]),
+            (FrameName::same("APPLE_GENRE_NAMES"), vec!["Electronic".to_string(), "Music".to_string()]),
+            (FrameName::same("APPLE_DISC_NUMBER"), vec!["1".to_string()]),
+        ]);
+        assert!(raw_attributes(&fixture_song(), " , =TAG").is_empty());
+    }
+
+    #[test]
+    fn genres_mode_english() {
+        let song = fixture_song();
+        assert_eq!(GenresMode::All.apply(&song), vec!["Electronic", "Music"]);
+        assert_eq!(GenresMode::WithoutMusic.apply(&song), vec!["Electronic"]);
+        assert_eq!(GenresMode::Primary.apply(&song), vec!["Electronic"]);
+    }
+
+    #[test]
+    fn genres_mode_localized() {
+        let song = fixture_song();
+        // Without genre relationship "Music" is found by English genre order
+        let mut localized = song.clone();
+        localized.attributes.genre_names = vec!["Électronique".to_string(), "Musique".to_string()];
+        assert_eq!(GenresMode::All.apply_localized(&song, &localized), vec!["Électronique", "Musique"]);
+        assert_eq!(GenresMode::WithoutMusic.apply_localized(&song, &localized), vec!["Électronique"]);
+
+        // Genre relationship is matched by ID regardless of order
+        localized.relationships.as_mut().unwrap().genres = Some(serde_json::from_str(r#"{"href": "", "data": [
+            {"id": "34", "href": "", "attributes": {"name": "Musique"}},
+            {"id": "1107", "href": "", "attributes": {"name": "House"}},
+            {"id": "7", "href": "", "attributes": {"name": "Électronique"}}
+        ]}"#).unwrap());
+        assert_eq!(GenresMode::WithoutMusic.apply_localized(&song, &localized), vec!["House", "Électronique"]);
+        assert_eq!(GenresMode::Primary.apply_localized(&song, &localized), vec!["House"]);
+    }
+}
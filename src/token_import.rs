@@ -0,0 +1,144 @@
+//! Media User Token import from desktop Apple Music clients (Cider, Cider 2, Apple Music Electron)
+
+use std::path::PathBuf;
+
+/// Config folder names of supported clients
+const CLIENT_DIRS: &[(&str, &str)] = &[
+    ("Cider 2", "sh.cider.genten"),
+    ("Cider 2", "sh.cider.electron"),
+    ("Cider", "Cider"),
+    ("Cider", "sidecar"),
+    ("Apple Music Electron", "apple-music-electron"),
+];
+/// Local storage key suffix of MusicKit's token
+const TOKEN_KEY: &str = "media-user-token";
+/// Max bytes of LevelDB record framing between key and value
+const MAX_VALUE_OFFSET: usize = 16;
+/// Shortest accepted token, real ones are a few hundred characters
+const MIN_TOKEN_LENGTH: usize = 100;
+
+/// Token found in a client's local storage
+#[derive(Debug, Clone)]
+pub struct ImportedToken {
+    /// Client name
+    pub source: String,
+    pub path: PathBuf,
+    pub token: String,
+}
+
+/// Find Media User Tokens stored by installed clients, newest one per client
+pub fn import_media_user_tokens() -> Vec<ImportedToken> {
+    let mut tokens = vec![];
+    for base in config_dirs() {
+        for (source, dir) in CLIENT_DIRS {
+            let leveldb = base.join(dir).join("Local Storage").join("leveldb");
+            let mut files: Vec<_> = match std::fs::read_dir(&leveldb) {
+                Ok(entries) => entries.filter_map(|e| e.ok()).map(|e| e.path())
+                    .filter(|p| p.extension().map(|e| e == "log" || e == "ldb").unwrap_or(false))
+                    .collect(),
+                Err(_) => continue,
+            };
+            // Newest value wins
+            files.sort_by_key(|p| std::fs::metadata(p).map(|m| m.modified().ok()).ok().flatten());
+            if let Some((path, token)) = files.into_iter().rev().find_map(|p| std::fs::read(&p).ok().map(|d| find_token(&d)).flatten().map(|t| (p, t))) {
+                debug!("Found Media User Token of {source} in {}", path.display());
+                tokens.push(ImportedToken { source: source.to_string(), path, token });
+            }
+        }
+    }
+    tokens
+}
+
+/// Application config folders of the platform
+fn config_dirs() -> Vec<PathBuf> {
+    let home = std::env::var_os("HOME").map(PathBuf::from);
+    let mut dirs = vec![];
+    if let Some(appdata) = std::env::var_os("APPDATA") {
+        dirs.push(PathBuf::from(appdata));
+    }
+    if let Some(config) = std::env::var_os("XDG_CONFIG_HOME") {
+        dirs.push(PathBuf::from(config));
+    }
+    if let Some(home) = home {
+        dirs.push(home.join(".config"));
+        dirs.push(home.join("Library").join("Application Support"));
+    }
+    dirs
+}
+
+/// Last token value after the local storage key in raw LevelDB data.
+/// Values can be stored as UTF-16, so NUL bytes are ignored.
+fn find_token(data: &[u8]) -> Option<String> {
+    let data: Vec<u8> = data.iter().copied().filter(|b| *b != 0).collect();
+    let key = TOKEN_KEY.as_bytes();
+    let mut found = None;
+    for start in (0..data.len().saturating_sub(key.len())).filter(|i| data[*i..].starts_with(key)) {
+        let rest = &data[start + key.len()..];
+        let offset = match rest.iter().take(MAX_VALUE_OFFSET).position(|b| is_token_byte(*b)) {
+            Some(offset) => offset,
+            None => continue,
+        };
+        let value: String = rest[offset..].iter()
+            .take_while(|b| is_token_byte(**b))
+            .map(|b| *b as char)
+            .collect();
+        if value.len() >= MIN_TOKEN_LENGTH {
+            found = Some(value);
+        }
+    }
+    found
+}
+
+fn is_token_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b == b'+' || b == b'/' || b == b'='
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(value: &str) -> Vec<u8> {
+        let mut data = b"_https://music.apple.com\x00\x01".to_vec();
+        data.extend_from_slice(TOKEN_KEY.as_bytes());
+        data.extend_from_slice(b"\x01\x00");
+        data.extend_from_slice(value.as_bytes());
+        data.push(b'"');
+        data
+    }
+
+    fn utf16(s: &str) -> Vec<u8> {
+        s.encode_utf16().flat_map(|c| c.to_le_bytes()).collect()
+    }
+
+    #[test]
+    fn utf8_record() {
+        let token = "A".repeat(120) + "+/=";
+        assert_eq!(find_token(&record(&token)), Some(token));
+    }
+
+    #[test]
+    fn utf16_record() {
+        let token = "Ab1".repeat(60);
+        let mut data = utf16("_https://music.apple.com");
+        data.extend(utf16(TOKEN_KEY));
+        data.extend_from_slice(&[0x01, 0x00]);
+        data.extend(utf16(&token));
+        assert_eq!(find_token(&data), Some(token));
+    }
+
+    #[test]
+    fn short_value_rejected() {
+        assert_eq!(find_token(&record("junk")), None);
+        assert_eq!(find_token(b"no key here"), None);
+    }
+
+    #[test]
+    fn last_value_wins() {
+        let old = "O".repeat(150);
+        let new = "N".repeat(150);
+        let mut data = record(&old);
+        data.extend(record(&new));
+        data.extend(record("short"));
+        assert_eq!(find_token(&data), Some(new));
+    }
+}
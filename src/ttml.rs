@@ -0,0 +1,244 @@
+//! TTML lyrics parser
+
+use std::error::Error;
+use serde::{Serialize, Deserialize};
+use quick_xml::Reader;
+use quick_xml::events::{Event, BytesStart};
+
+/// Open element inside body
+enum Element {
+    Body,
+    Div,
+    Line,
+    /// Word, background vocals are spans wrapping word spans
+    Span(KaraokeWord),
+    Other,
+}
+
+/// Parse TTML from Apple Music
+pub fn parse_ttml(ttml: &str, language: &str) -> Result<KaraokeLyrics, Box<dyn Error>> {
+    let mut reader = Reader::from_str(ttml);
+    let mut paragraphs = vec![];
+    let mut paragraph = vec![];
+    let mut line: Option<KaraokeLine> = None;
+    // Empty until body start
+    let mut stack: Vec<Element> = vec![];
+
+    loop {
+        let (start, empty) = match reader.read_event()? {
+            Event::Start(start) => (start, false),
+            // Self closing elements are opened and closed at once
+            Event::Empty(start) => (start, true),
+            Event::End(_) => {
+                if close(&mut stack, &mut line, &mut paragraph, &mut paragraphs)? {
+                    break;
+                }
+                continue;
+            },
+            Event::Text(text) => {
+                let text = text.unescape()?;
+                if text.trim().is_empty() {
+                    continue;
+                }
+                match stack.last_mut() {
+                    // Synced
+                    Some(Element::Span(word)) => word.text.push_str(&text),
+                    // Unsynced
+                    Some(Element::Line) => {
+                        if let Some(line) = line.as_mut().filter(|l| l.words.is_empty()) {
+                            line.text.push_str(&text);
+                        }
+                    },
+                    _ => {}
+                }
+                continue;
+            },
+            Event::Eof => break,
+            _ => continue
+        };
+        let element = match start.local_name().as_ref() {
+            b"body" => Element::Body,
+            // Head with metadata
+            _ if stack.is_empty() => continue,
+            b"div" => Element::Div,
+            b"p" => {
+                let mut new = KaraokeLine::default();
+                for (key, value) in attributes(&start)? {
+                    match key.as_str() {
+                        "begin" => new.start = Some(parse_timestamp(&value)?),
+                        "end" => new.end = Some(parse_timestamp(&value)?),
+                        "agent" => new.agent = Some(value),
+                        _ => {}
+                    }
+                }
+                line = Some(new);
+                Element::Line
+            },
+            // Inherit background flag from wrapper
+            b"span" => {
+                let background = match stack.last() {
+                    Some(Element::Span(wrapper)) => wrapper.background,
+                    _ => false
+                };
+                let mut word = KaraokeWord { background, ..Default::default() };
+                for (key, value) in attributes(&start)? {
+                    match key.as_str() {
+                        "begin" => word.start = Some(parse_timestamp(&value)?),
+                        "end" => word.end = Some(parse_timestamp(&value)?),
+                        "role" if value == "x-bg" => word.background = true,
+                        _ => {}
+                    }
+                }
+                Element::Span(word)
+            },
+            _ => Element::Other,
+        };
+        stack.push(element);
+        if empty && close(&mut stack, &mut line, &mut paragraph, &mut paragraphs)? {
+            break;
+        }
+    }
+
+    // Create lyrics
+    Ok(KaraokeLyrics { version: KaraokeLyrics::VERSION, paragraphs, language: language.to_owned() })
+}
+
+/// Close the innermost open element, returns whether the body ended
+fn close(stack: &mut Vec<Element>, line: &mut Option<KaraokeLine>, paragraph: &mut Vec<KaraokeLine>, paragraphs: &mut Vec<Vec<KaraokeLine>>) -> Result<bool, Box<dyn Error>> {
+    match stack.pop() {
+        Some(Element::Body) => return Ok(true),
+        // End of paragraph
+        Some(Element::Div) => {
+            *line = None;
+            paragraphs.push(std::mem::take(paragraph));
+        },
+        // End of line, merge text from words
+        Some(Element::Line) => {
+            if let Some(mut line) = line.take() {
+                if line.text.is_empty() {
+                    line.text = line.words.iter().map(|w| w.text.as_str()).collect::<Vec<_>>().join(" ");
+                }
+                paragraph.push(line);
+            }
+        },
+        // End of word, wrappers without own text are dropped
+        Some(Element::Span(word)) => {
+            if !word.text.is_empty() {
+                line.as_mut().ok_or("Word outside of line")?.words.push(word);
+            }
+        },
+        Some(Element::Other) | None => {}
+    }
+    Ok(false)
+}
+
+/// Unescaped attributes by local name
+fn attributes(element: &BytesStart) -> Result<Vec<(String, String)>, Box<dyn Error>> {
+    let mut out = vec![];
+    for attribute in element.attributes() {
+        let attribute = attribute?;
+        let key = String::from_utf8_lossy(attribute.key.local_name().as_ref()).to_string();
+        out.push((key, attribute.unescape_value()?.to_string()));
+    }
+    Ok(out)
+}
+
+/// Lyrics with per-word timing, exported as JSON in the following schema:
+/// ```json
+/// {
+///     "version": 1,
+///     "language": "en_GB",
+///     "paragraphs": [[{
+///         "text": "Full line",
+///         "start": 1000,
+///         "end": 2500,
+///         "agent": "v1",
+///         "words": [{ "text": "Full", "start": 1000, "end": 1500, "background": false }]
+///     }]]
+/// }
+/// ```
+/// Times are in milliseconds and `null` when unsynced. `agent` is the TTML singer ID (`v1`, `v2`, ...),
+/// `background` marks backing vocals.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KaraokeLyrics {
+    pub version: u32,
+    pub language: String,
+    pub paragraphs: Vec<Vec<KaraokeLine>>
+}
+
+impl KaraokeLyrics {
+    /// Current JSON schema version
+    pub const VERSION: u32 = 1;
+
+    /// Does any line have timing
+    pub fn synced(&self) -> bool {
+        self.paragraphs.iter().flatten().any(|l| l.start.is_some())
+    }
+
+    /// Format as LRC, unsynced lines without timestamp
+    pub fn to_lrc(&self) -> String {
+        let mut out = String::new();
+        for (i, paragraph) in self.paragraphs.iter().enumerate() {
+            if i > 0 {
+                out.push('\n');
+            }
+            for line in paragraph {
+                if let Some(start) = line.start {
+                    out.push_str(&format!("[{:02}:{:02}.{:02}]", start / 60000, (start / 1000) % 60, (start % 1000) / 10));
+                }
+                out.push_str(&line.text);
+                out.push('\n');
+            }
+        }
+        out
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct KaraokeLine {
+    pub text: String,
+    pub start: Option<u64>,
+    pub end: Option<u64>,
+    pub agent: Option<String>,
+    pub words: Vec<KaraokeWord>
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct KaraokeWord {
+    pub text: String,
+    pub start: Option<u64>,
+    pub end: Option<u64>,
+    pub background: bool
+}
+
+/// Parse TTML timestamp (`1:02:03.456`, `02:03.456`, `3.456`, `3.456s`) to milliseconds
+fn parse_timestamp(timestamp: &str) -> Result<u64, Box<dyn Error>> {
+    let mut ms = 0.0;
+    for part in timestamp.trim().trim_end_matches('s').split(':') {
+        let value: f64 = part.parse().map_err(|_| format!("Invalid timestamp: {timestamp}"))?;
+        ms = ms * 60.0 + value;
+    }
+    Ok((ms * 1000.0).round() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(text: &str, start: Option<u64>) -> KaraokeLine {
+        KaraokeLine { text: text.to_string(), start, ..Default::default() }
+    }
+
+    #[test]
+    fn lrc_timestamps_and_paragraphs() {
+        let lyrics = KaraokeLyrics {
+            version: KaraokeLyrics::VERSION,
+            language: "en_GB".to_string(),
+            paragraphs: vec![
+                vec![line("One", Some(1000)), line("Unsynced", None)],
+                vec![line("Two", Some(61234))],
+            ],
+        };
+        assert_eq!(lyrics.to_lrc(), "[00:01.00]One\nUnsynced\n\n[01:01.23]Two\n");
+    }
+}
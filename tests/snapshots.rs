@@ -0,0 +1,88 @@
+//! Snapshot tests of the conversion pipelines using recorded API responses from `tests/fixtures`.
+//!
+//! Missing snapshots fail, run with `UPDATE_SNAPSHOTS=1` to record new ones or accept intended changes.
+//!
+//! The committed snapshots were written by hand and have not been checked against a `cargo test` run yet,
+//! re-record them with `UPDATE_SNAPSHOTS=1` and review the diff on the first full build.
+
+use std::path::Path;
+use serde::Serialize;
+use serde_json::{json, Value};
+use onetagger_apple_music::models::{AlbumAttributes, DataResponse};
+use onetagger_apple_music::{parse_ttml, AlbumReport};
+
+/// Compare value serialized to JSON with `tests/snapshots/<name>.json`
+fn assert_snapshot<T: Serialize>(name: &str, value: &T) {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("snapshots").join(format!("{name}.json"));
+    let actual = serde_json::to_value(value).unwrap();
+    if std::env::var("UPDATE_SNAPSHOTS").is_ok() {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, serde_json::to_string_pretty(&actual).unwrap()).unwrap();
+        eprintln!("Recorded snapshot: {}", path.display());
+        return;
+    }
+    assert!(path.exists(), "Snapshot {name} is missing, run with UPDATE_SNAPSHOTS=1 to record it");
+    let expected: Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    assert!(actual == expected, "Snapshot {name} differs, rerun with UPDATE_SNAPSHOTS=1 if intended\nactual: {}",
+        serde_json::to_string_pretty(&actual).unwrap());
+}
+
+#[test]
+fn lyrics_ttml() {
+    let response: Value = serde_json::from_str(include_str!("fixtures/lyrics.json")).unwrap();
+    let ttml = response["data"][0]["attributes"]["ttml"].as_str().unwrap();
+    let lyrics = parse_ttml(ttml, "en_GB").unwrap();
+    assert!(lyrics.synced());
+    assert_snapshot("lyrics", &lyrics);
+}
+
+#[test]
+fn album() {
+    let response: DataResponse<AlbumAttributes> = serde_json::from_str(include_str!("fixtures/album.json")).unwrap();
+    let album = &response.data[0];
+    assert_snapshot("album", &json!({
+        "id": album.id,
+        "name": album.attributes.name,
+        "artists": album.artists(),
+        "label": album.label(),
+        "releaseType": album.release_type(),
+        "discTotal": album.disc_total(),
+        "compilation": album.compilation(),
+        "upc": album.attributes.upc,
+    }));
+}
+
+#[test]
+fn album_report() {
+    let response: DataResponse<AlbumAttributes> = serde_json::from_str(include_str!("fixtures/album.json")).unwrap();
+    assert_snapshot("album_report", &AlbumReport::new(&response.data[0]));
+}
+
+#[cfg(feature = "onetagger")]
+#[test]
+fn search_tracks() {
+    use onetagger_apple_music::models::SearchResultsResponse;
+    use onetagger_tagger::Track;
+
+    let response: SearchResultsResponse = serde_json::from_str(include_str!("fixtures/search.json")).unwrap();
+    // Malformed item is skipped
+    assert_eq!(response.results.song.data.len(), 2);
+    let tracks: Vec<Value> = response.results.song.data.into_iter().map(|s| {
+        let track: Track = s.into();
+        json!({
+            "title": track.title,
+            "artists": track.artists,
+            "albumArtists": track.album_artists,
+            "album": track.album,
+            "label": track.label,
+            "trackId": track.track_id,
+            "releaseId": track.release_id,
+            "durationMs": track.duration.as_millis() as u64,
+            "discNumber": track.disc_number,
+            "trackTotal": track.track_total,
+            "isrc": track.isrc,
+            "url": track.url,
+        })
+    }).collect();
+    assert_snapshot("search_tracks", &tracks);
+}
@@ -0,0 +1,180 @@
+//! Comparison of the quick-xml TTML parser with the previous xmlparser implementation, kept here as reference
+
+use std::error::Error;
+use serde_json::Value;
+use xmlparser::{Tokenizer, Token, ElementEnd};
+use onetagger_apple_music::{parse_ttml, KaraokeLyrics, KaraokeLine, KaraokeWord};
+
+/// Parser before the move to quick-xml
+fn legacy_parse_ttml(ttml: &str, language: &str) -> Result<KaraokeLyrics, Box<dyn Error>> {
+    let mut is_body = false;
+    let mut last_element = String::new();
+
+    let mut paragraphs = vec![];
+    let mut paragraph = vec![];
+    let mut line: Option<KaraokeLine> = None;
+    let mut spans: Vec<KaraokeWord> = vec![];
+
+    for token in Tokenizer::from(ttml) {
+        let token = token?;
+        match token {
+            Token::ElementStart { local, .. } => {
+                last_element = local.to_string();
+                if local.as_str() == "body" {
+                    is_body = true;
+                    continue;
+                }
+                if !is_body {
+                    continue;
+                }
+                match local.as_str() {
+                    "p" => {
+                        line = Some(KaraokeLine::default());
+                        spans.clear();
+                    },
+                    "span" => {
+                        let background = spans.last().map(|s| s.background).unwrap_or(false);
+                        spans.push(KaraokeWord { background, ..Default::default() });
+                    },
+                    _ => {}
+                }
+            },
+            Token::Attribute { local, value, .. } => {
+                if let Some(word) = spans.last_mut() {
+                    match local.as_str() {
+                        "begin" => word.start = Some(parse_timestamp(&value)?),
+                        "end" => word.end = Some(parse_timestamp(&value)?),
+                        "role" if value.as_str() == "x-bg" => word.background = true,
+                        _ => {}
+                    }
+                    continue;
+                }
+                if let Some(line) = line.as_mut() {
+                    match local.as_str() {
+                        "begin" => line.start = Some(parse_timestamp(&value)?),
+                        "end" => line.end = Some(parse_timestamp(&value)?),
+                        "agent" => line.agent = Some(value.to_string()),
+                        _ => {}
+                    }
+                }
+            },
+            Token::ElementEnd { end, .. } => {
+                match end {
+                    ElementEnd::Close(_, local) if local.as_str() == "body" => break,
+                    ElementEnd::Close(_, local) if local.as_str() == "p" => {
+                        if let Some(mut line) = line.take() {
+                            if line.text.is_empty() {
+                                line.text = line.words.iter().map(|w| w.text.as_str()).collect::<Vec<_>>().join(" ");
+                            }
+                            paragraph.push(line);
+                        }
+                        spans.clear();
+                    },
+                    ElementEnd::Close(_, local) if local.as_str() == "span" => {
+                        if let Some(word) = spans.pop() {
+                            if !word.text.is_empty() {
+                                line.as_mut().ok_or("Word outside of line")?.words.push(word);
+                            }
+                        }
+                    },
+                    ElementEnd::Empty if last_element == "span" => {
+                        spans.pop();
+                    },
+                    ElementEnd::Close(_, local) if local.as_str() == "div" => {
+                        line = None;
+                        spans.clear();
+                        paragraphs.push(std::mem::take(&mut paragraph));
+                    }
+                    _ => continue
+                }
+            },
+            Token::Text { text } => {
+                if text.trim().is_empty() {
+                    continue;
+                }
+                if let Some(word) = spans.last_mut() {
+                    word.text = text.as_str().to_string();
+                    continue;
+                }
+                if let Some(line) = line.as_mut() {
+                    if line.words.is_empty() {
+                        line.text = text.as_str().to_string();
+                    }
+                }
+            },
+            _ => continue
+        }
+    }
+    Ok(KaraokeLyrics { version: KaraokeLyrics::VERSION, paragraphs, language: language.to_owned() })
+}
+
+fn parse_timestamp(timestamp: &str) -> Result<u64, Box<dyn Error>> {
+    let mut ms = 0.0;
+    for part in timestamp.trim().trim_end_matches('s').split(':') {
+        let value: f64 = part.parse().map_err(|_| format!("Invalid timestamp: {timestamp}"))?;
+        ms = ms * 60.0 + value;
+    }
+    Ok((ms * 1000.0).round() as u64)
+}
+
+/// Both parsers give the same JSON
+fn assert_same(ttml: &str) -> KaraokeLyrics {
+    let lyrics = parse_ttml(ttml, "en_GB").unwrap();
+    let legacy = legacy_parse_ttml(ttml, "en_GB").unwrap();
+    assert_eq!(serde_json::to_value(&lyrics).unwrap(), serde_json::to_value(&legacy).unwrap());
+    lyrics
+}
+
+/// Synced TTML with `divs` paragraphs of `lines` lines, each with words and a background vocal wrapper
+fn generated_ttml(divs: usize, lines: usize) -> String {
+    let mut ttml = String::from(r#"<tt xmlns="http://www.w3.org/ns/ttml" xmlns:ttm="http://www.w3.org/ns/ttml#metadata" xmlns:itunes="http://music.apple.com/lyric-ttml-internal" itunes:timing="Word"><head><metadata><ttm:agent type="person" xml:id="v1"/></metadata></head><body>"#);
+    let mut time = 0;
+    let timestamp = |ms: u64| format!("{}:{:02}.{:03}", ms / 60000, (ms / 1000) % 60, ms % 1000);
+    for d in 0..divs {
+        ttml.push_str(&format!("\n<div begin=\"{}\">", timestamp(time)));
+        for l in 0..lines {
+            ttml.push_str(&format!("\n<p begin=\"{}\" end=\"{}\" ttm:agent=\"v{}\">", timestamp(time), timestamp(time + 1750), l % 2 + 1));
+            for w in 0..5 {
+                ttml.push_str(&format!("<span begin=\"{}\" end=\"{}\">word{d}_{l}_{w}</span> ", timestamp(time), timestamp(time + 250)));
+                time += 250;
+            }
+            ttml.push_str(&format!("<span ttm:role=\"x-bg\"><span begin=\"{}\" end=\"{}\">(bg{l})</span></span></p>", timestamp(time), timestamp(time + 500)));
+            time += 500;
+        }
+        ttml.push_str("\n</div>");
+    }
+    ttml.push_str("\n</body></tt>");
+    ttml
+}
+
+#[test]
+fn recorded_lyrics() {
+    let response: Value = serde_json::from_str(include_str!("fixtures/lyrics.json")).unwrap();
+    assert_same(response["data"][0]["attributes"]["ttml"].as_str().unwrap());
+}
+
+#[test]
+fn nested_background_vocals() {
+    let lyrics = assert_same(include_str!("fixtures/nested.ttml"));
+    assert_eq!(lyrics.paragraphs.len(), 3);
+    let first = &lyrics.paragraphs[0][0];
+    assert_eq!(first.text, "First line (one two three) again");
+    assert_eq!(first.words.iter().filter(|w| w.background).count(), 3);
+    assert_eq!(lyrics.paragraphs[2][1].text, "Another unsynced line");
+}
+
+#[test]
+fn thousands_of_lines() {
+    let ttml = generated_ttml(40, 100);
+    assert!(ttml.lines().count() > 4000);
+    let lyrics = assert_same(&ttml);
+    assert_eq!(lyrics.paragraphs.iter().flatten().count(), 4000);
+}
+
+#[test]
+fn escaped_text() {
+    // Intended difference, the old parser kept entities
+    let ttml = r#"<tt><body><div><p begin="1.000" end="2.000"><span begin="1.000" end="2.000">Rock &amp; Roll</span></p></div></body></tt>"#;
+    assert_eq!(legacy_parse_ttml(ttml, "en_GB").unwrap().paragraphs[0][0].text, "Rock &amp; Roll");
+    assert_eq!(parse_ttml(ttml, "en_GB").unwrap().paragraphs[0][0].text, "Rock & Roll");
+}